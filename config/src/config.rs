@@ -2,12 +2,29 @@ use std::path::Path;
 use log::debug;
 use urlencoding;
 use rtdlib::types::*;
+use crate::error::ConfigError;
+
+// Placeholders of the mongo url, replaced by environment variables
+static MONGO_VARIABLES: [(&str, &str); 4] = [
+    ("%USERNAME%", "MDB_USER"),
+    ("%PASSWORD%", "MDB_PASSWORD"),
+    ("%ADDRESS%", "MDB_ADDRESS"),
+    ("%PORT%", "MDB_PORT")
+];
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    toml: toml::Value,
+    log: Option<Log>,
+    proxy: Option<AddProxy>,
     pub sender: Sender,
     pub mongo: Mongo,
+    pub storage: Storage,
+    pub moderation: Moderation,
+    pub report: Report,
+    pub private: Private,
+    pub retention: Retention,
+    pub renames: Renames,
+    pub stats: Stats,
     pub keywords_path: String,
     pub message_path: String,
     pub scammer_account_path: String,
@@ -15,48 +32,158 @@ pub struct Config {
     pub session_path: String
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        let toml_file = match hostname::get_hostname() {
-            Some(name) => format!("telegram-client.{}.toml", name),
-            None => "telegram-client.toml".to_string()
-        };
-        let mut toml_file = Path::new("config/res").join(&toml_file[..]);
-        if !toml_file.exists() {
-            toml_file = toolkit::path::root_dir().join("config/res").join("telegram-client.toml");
+/**
+*   Type of a value read from the config file
+*/
+trait Value: Sized {
+
+    // Name of the type in the errors
+    const TYPE: &'static str;
+
+    fn read(value: &toml::Value) -> Option<Self>;
+
+}
+
+impl Value for bool {
+    const TYPE: &'static str = "boolean";
+
+    fn read(value: &toml::Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl Value for i64 {
+    const TYPE: &'static str = "integer";
+
+    fn read(value: &toml::Value) -> Option<Self> {
+        value.as_integer()
+    }
+}
+
+impl Value for u64 {
+    const TYPE: &'static str = "non-negative integer";
+
+    fn read(value: &toml::Value) -> Option<Self> {
+        value.as_integer().filter(|&v| v >= 0).map(|v| v as u64)
+    }
+}
+
+impl Value for f64 {
+    const TYPE: &'static str = "float";
+
+    fn read(value: &toml::Value) -> Option<Self> {
+        value.as_float()
+    }
+}
+
+impl Value for String {
+    const TYPE: &'static str = "string";
+
+    fn read(value: &toml::Value) -> Option<Self> {
+        value.as_str().map(String::from)
+    }
+}
+
+fn read<T: Value>(key: String, value: &toml::Value) -> Result<T, ConfigError> {
+    T::read(value).ok_or_else(|| ConfigError::WrongType { key, expected: T::TYPE, found: value.type_str() })
+}
+
+// One of the names accepted for a key
+fn choice<T: Clone>(key: String, name: &str, choices: &[(&str, T)]) -> Result<T, ConfigError> {
+    choices.iter()
+        .find(|(choice, _)| *choice == name)
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| ConfigError::Invalid {
+            key,
+            message: format!("'{}' isn't one of {}", name, choices.iter().map(|(choice, _)| *choice).collect::<Vec<&str>>().join(", "))
+        })
+}
+
+/**
+*   A table of the config file, read with the path of its keys so the errors point at them
+*/
+struct Section<'a> {
+    path: String,
+    table: Option<&'a toml::value::Table>
+}
+
+impl<'a> Section<'a> {
+
+    fn new(path: String, value: Option<&'a toml::Value>) -> Result<Self, ConfigError> {
+        match value {
+            None => Ok(Section { path, table: None }),
+            Some(toml::Value::Table(table)) => Ok(Section { path, table: Some(table) }),
+            Some(other) => Err(ConfigError::WrongType { key: path, expected: "table", found: other.type_str() })
         }
-        if !toml_file.exists() {
-            panic!("Not found config file");
+    }
+
+    // A missing section is empty, its keys take their default
+    fn optional(toml: &'a toml::Value, name: &str) -> Result<Self, ConfigError> {
+        Section::new(name.to_string(), toml.get(name))
+    }
+
+    fn required(toml: &'a toml::Value, name: &str) -> Result<Self, ConfigError> {
+        let section = Section::optional(toml, name)?;
+        match section.table {
+            Some(_) => Ok(section),
+            None => Err(ConfigError::Missing { key: name.to_string() })
         }
-        debug!("Use {:?} config file", toml_file);
-        let toml = std::fs::read_to_string(toml_file).unwrap();
-        Config::new(toml)
     }
-}
 
-fn get_paths(toml: &toml::Value) -> (String, String, String, String, String) {
-    if let Some(table) = toml.get("paths")
-        .filter(|&v| v.is_table())
-        .map(|v| v.as_table())
-        .filter(|&v| v.is_some())
-        .map(|v| v.unwrap()) {
+    fn is_set(&self) -> bool {
+        self.table.is_some()
+    }
 
-        let keywords = table.get("keywords").unwrap().as_str().unwrap();
-        let message = table.get("message").unwrap().as_str().unwrap();
-        let scammer_account = table.get("scammer-account").unwrap().as_str().unwrap();
-        let about = table.get("about").unwrap().as_str().unwrap();
-        let session = table.get("session").unwrap().as_str().unwrap();
+    fn key(&self, key: &str) -> String {
+        format!("{}.{}", self.path, key)
+    }
 
-        return (
-            String::from(keywords),
-            String::from(message),
-            String::from(scammer_account),
-            String::from(about),
-            String::from(session)
-        );
-    } else {
-        panic!("Missing path variables in config file");
+    fn value(&self, key: &str) -> Option<&'a toml::Value> {
+        self.table.and_then(|table| table.get(key))
     }
+
+    fn get<T: Value>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        self.value(key).map(|value| read(self.key(key), value)).transpose()
+    }
+
+    fn require<T: Value>(&self, key: &str) -> Result<T, ConfigError> {
+        self.get(key)?.ok_or_else(|| ConfigError::Missing { key: self.key(key) })
+    }
+
+    fn or<T: Value>(&self, key: &str, default: T) -> Result<T, ConfigError> {
+        Ok(self.get(key)?.unwrap_or(default))
+    }
+
+    // Items are read with their index, e.g. `moderation.keyword[1]`
+    fn list(&self, key: &str) -> Result<Option<Vec<(String, &'a toml::Value)>>, ConfigError> {
+        match self.value(key) {
+            None => Ok(None),
+            Some(toml::Value::Array(items)) => Ok(Some(items.iter()
+                .enumerate()
+                .map(|(index, item)| (format!("{}[{}]", self.key(key), index), item))
+                .collect())),
+            Some(other) => Err(ConfigError::WrongType { key: self.key(key), expected: "list", found: other.type_str() })
+        }
+    }
+
+    // Array of tables, e.g. `[[retention.rules]]`
+    fn sections(&self, key: &str) -> Result<Vec<Section<'a>>, ConfigError> {
+        self.list(key)?.unwrap_or_default().into_iter()
+            .map(|(path, item)| Section::new(path, Some(item)))
+            .collect()
+    }
+
+}
+
+fn get_paths(toml: &toml::Value) -> Result<(String, String, String, String, String), ConfigError> {
+    let paths = Section::required(toml, "paths")?;
+    Ok((
+        paths.require("keywords")?,
+        paths.require("message")?,
+        paths.require("scammer-account")?,
+        paths.require("about")?,
+        paths.require("session")?
+    ))
 }
 
 impl Config {
@@ -66,9 +193,17 @@ impl Config {
     */
     pub fn empty() -> Self {
         Config {
-            toml: toml::Value::from(0),
-            sender: Sender { send: false, min_wait: 0.0, max_wait: 0.0, timeout: 0.0 },
-            mongo: Mongo { url: String::new() },
+            log: None,
+            proxy: None,
+            sender: Sender { send: false, min_wait: 0.0, max_wait: 0.0, timeout: 0.0, warning_lifetime: 0.0 },
+            mongo: Mongo::default(),
+            storage: Storage::default(),
+            moderation: Moderation::default(),
+            report: Report::default(),
+            private: Private::default(),
+            retention: Retention::default(),
+            renames: Renames::default(),
+            stats: Stats::default(),
             keywords_path: String::new(),
             message_path: String::new(),
             scammer_account_path: String::new(),
@@ -77,152 +212,248 @@ impl Config {
         }
     }
 
-    fn new<S: AsRef<str>>(toml: S) -> Self {
-        let value: toml::Value = toml::from_str(toml.as_ref()).unwrap();
+    fn new<S: AsRef<str>>(toml: S) -> Result<Self, ConfigError> {
+        let value: toml::Value = toml::from_str(toml.as_ref()).map_err(|e| ConfigError::Syntax { message: e.to_string() })?;
         let (
             keywords_path,
             message_path,
             scammer_account_path,
             about_path,
             session_path
-        ) = get_paths(&value);
+        ) = get_paths(&value)?;
+        let storage = get_storage(&value)?;
 
-        Self {
-            toml: value.clone(),
-            sender: get_sender(&value),
-            mongo: get_mongo(&value),
+        Ok(Self {
+            log: get_log(&value)?,
+            proxy: get_proxy(&value)?,
+            sender: get_sender(&value)?,
+            mongo: get_mongo(&value, storage.backend)?,
+            storage,
+            moderation: get_moderation(&value)?,
+            report: get_report(&value)?,
+            private: get_private(&value)?,
+            retention: get_retention(&value)?,
+            renames: get_renames(&value)?,
+            stats: get_stats(&value)?,
             keywords_path,
             message_path,
             scammer_account_path,
             about_path,
             session_path
-        }
+        })
     }
 
-    pub fn from(path: &str) -> Self {
-        let toml_file = Path::new(path);
-
+    /**
+    *   Loads the config file of this host, or the default one, from `config/res`
+    */
+    pub fn discover() -> Result<Self, ConfigError> {
+        let toml_file = match hostname::get_hostname() {
+            Some(name) => format!("telegram-client.{}.toml", name),
+            None => "telegram-client.toml".to_string()
+        };
+        let mut toml_file = Path::new("config/res").join(&toml_file[..]);
         if !toml_file.exists() {
-            panic!("Config file '{}' not found", path);
+            toml_file = toolkit::path::root_dir().join("config/res").join("telegram-client.toml");
         }
+        let path = toml_file.to_string_lossy().to_string();
+        if !toml_file.exists() {
+            return Err(ConfigError::File { path, message: String::from("Not found") });
+        }
+        Config::from(&path)
+    }
 
+    pub fn from(path: &str) -> Result<Self, ConfigError> {
+        let toml_file = Path::new(path);
         debug!("Use {:?} config file", toml_file);
 
-        let toml = std::fs::read_to_string(toml_file).unwrap();
-
+        let toml = std::fs::read_to_string(toml_file).map_err(|e| ConfigError::File { path: path.to_string(), message: e.to_string() })?;
         Config::new(toml)
     }
 
     pub fn proxy(&self) -> Option<AddProxy> {
-        self.toml.get("proxy")
-            .filter(|&v| v.is_table())
-            .map(|v| v.as_table())
-            .filter(|v| v.is_some())
-            .map(|v| v.unwrap())
-            .map(|v| {
-                let server = v.get("server").unwrap().as_str().unwrap();
-                let port = v.get("port").unwrap().as_integer().unwrap();
-                let enable = v.get("enable").unwrap().as_bool().unwrap();
-                let type_ = v.get("type").unwrap().as_str().unwrap();
-                let mut tga = AddProxy::builder();
-                tga.server(server)
-                    .port(port as i64)
-                    .enable(enable);
-                match type_ {
-                    "socks5" => tga.type_(ProxyType::socks5(ProxyTypeSocks5::builder())),
-                    "http" => tga.type_(ProxyType::http(ProxyTypeHttp::builder())),
-                    "mtproto" => tga.type_(ProxyType::mtproto(ProxyTypeMtproto::builder())),
-                    _ => panic!("Not found proxy type")
-                };
-                tga.build()
-            })
+        self.proxy.clone()
     }
 
     pub fn log(&self) -> Option<Log> {
-        self.toml.get("log")
-            .filter(|&v| v.is_table())
-            .map(|v| v.as_table())
-            .filter(|&v| v.is_some())
-            .map(|v| v.unwrap())
-            .map(|v| {
-                let type_ = match v.get("type").unwrap().as_str().unwrap() {
-                    "console" => LogType::Console,
-                    "file" => LogType::File,
-                    _ => LogType::Console
-                };
-                let path = v.get("path").filter(|&v| v.is_str())
-                    .map(|v| v.as_str())
-                    .filter(|&v| v.is_some())
-                    .map(|v| v.unwrap().to_string());
-                let level = v.get("level").filter(|&v| v.is_integer())
-                    .map(|v| v.as_integer())
-                    .map(|v| v.unwrap())
-                    .map_or(1, |v| v);
-                Log { type_, path, level }
-            })
+        self.log.clone()
     }
 
 }
 
-fn get_sender(toml: &toml::Value) -> Sender {
-    toml.get("sender")
-        .filter(|&v| v.is_table())
-        .map(|v| v.as_table())
-        .filter(|&v| v.is_some())
-        .map(|v| v.unwrap())
-        .map(|v| {
-            let send = v.get("send").filter(|&v| v.is_bool())
-                .map(|v| v.as_bool())
-                .filter(|&v| v.is_some())
-                .map(|v| v.unwrap())
-                .unwrap();
-            let min_wait = v.get("min-wait").filter(|&v| v.is_float())
-                .map(|v| v.as_float())
-                .filter(|&v| v.is_some())
-                .map(|v| v.unwrap())
-                .unwrap();
-            let max_wait = v.get("max-wait").filter(|&v| v.is_float())
-                .map(|v| v.as_float())
-                .filter(|&v| v.is_some())
-                .map(|v| v.unwrap())
-                .unwrap();
-            let timeout = v.get("timeout").filter(|&v| v.is_float())
-                .map(|v| v.as_float())
-                .filter(|&v| v.is_some())
-                .map(|v| v.unwrap())
-                .unwrap();
-
-            Sender { send, min_wait, max_wait, timeout }
-        })
-        .unwrap()
-}
-
-fn get_mongo(toml: &toml::Value) -> Mongo {
-    toml.get("mongo")
-        .filter(|&v| v.is_table())
-        .map(|v| v.as_table())
-        .filter(|&v| v.is_some())
-        .map(|v| v.unwrap())
-        .map(|v| {
-            let url = v.get("url").filter(|&v| v.is_str())
-                .map(|v| v.as_str())
-                .filter(|&v| v.is_some())
-                .map(|v| v.unwrap())
-                .unwrap();
-
-            let mdb_user = urlencoding::encode(&std::env::var("MDB_USER").unwrap());
-            let mdb_password = urlencoding::encode(&std::env::var("MDB_PASSWORD").unwrap());
-            let mdb_address = urlencoding::encode(&std::env::var("MDB_ADDRESS").unwrap());
-            let mdb_port = urlencoding::encode(&std::env::var("MDB_PORT").unwrap());
-
-            let url = url.replace("%USERNAME%", &mdb_user);
-            let url = url.replace("%PASSWORD%", &mdb_password);
-            let url = url.replace("%ADDRESS%", &mdb_address);
-            let url = url.replace("%PORT%", &mdb_port);
-
-            Mongo { url }
+fn get_proxy(toml: &toml::Value) -> Result<Option<AddProxy>, ConfigError> {
+    let proxy = Section::optional(toml, "proxy")?;
+    if !proxy.is_set() {
+        return Ok(None);
+    }
+
+    let mut tga = AddProxy::builder();
+    tga.server(proxy.require::<String>("server")?)
+        .port(proxy.require::<i64>("port")?)
+        .enable(proxy.require::<bool>("enable")?);
+    match proxy.require::<String>("type")?.as_str() {
+        "socks5" => tga.type_(ProxyType::socks5(ProxyTypeSocks5::builder())),
+        "http" => tga.type_(ProxyType::http(ProxyTypeHttp::builder())),
+        "mtproto" => tga.type_(ProxyType::mtproto(ProxyTypeMtproto::builder())),
+        other => return Err(ConfigError::Invalid { key: proxy.key("type"), message: format!("'{}' isn't one of socks5, http, mtproto", other) })
+    };
+    Ok(Some(tga.build()))
+}
+
+fn get_log(toml: &toml::Value) -> Result<Option<Log>, ConfigError> {
+    let log = Section::optional(toml, "log")?;
+    if !log.is_set() {
+        return Ok(None);
+    }
+
+    let type_ = choice(log.key("type"), &log.or("type", String::from("console"))?, &[("console", LogType::Console), ("file", LogType::File)])?;
+    let path = log.get("path")?;
+    let level = log.or("level", 1)?;
+    let sensitive = log.or("sensitive", false)?;
+    Ok(Some(Log { type_, path, level, sensitive }))
+}
+
+fn get_sender(toml: &toml::Value) -> Result<Sender, ConfigError> {
+    let sender = Section::required(toml, "sender")?;
+    Ok(Sender {
+        send: sender.require("send")?,
+        min_wait: sender.require("min-wait")?,
+        max_wait: sender.require("max-wait")?,
+        timeout: sender.require("timeout")?,
+        warning_lifetime: sender.or("warning-lifetime", 0.0)?
+    })
+}
+
+// The url is only needed, and its placeholders only replaced, when the records are stored in MongoDB
+fn get_mongo(toml: &toml::Value, backend: Backend) -> Result<Mongo, ConfigError> {
+    let mongo = Section::optional(toml, "mongo")?;
+    let default = Mongo::default();
+
+    let mut url = match backend {
+        Backend::Mongo => mongo.require::<String>("url")?,
+        Backend::Sqlite => mongo.or("url", default.url)?
+    };
+    if backend == Backend::Mongo {
+        for (placeholder, variable) in MONGO_VARIABLES.iter() {
+            if url.contains(placeholder) {
+                let value = std::env::var(variable).map_err(|_| ConfigError::Env { key: mongo.key("url"), variable: variable.to_string() })?;
+                url = url.replace(placeholder, &urlencoding::encode(&value));
+            }
+        }
+    }
+
+    let database = mongo.or("database", default.database)?;
+    let prefix = mongo.or("prefix", default.prefix)?;
+    // MongoDB rejects these characters in database and collection names
+    if database.is_empty() || database.contains(|c: char| "/\\. \"$".contains(c)) {
+        return Err(ConfigError::Invalid { key: mongo.key("database"), message: format!("'{}' can't be empty or contain / \\ . \" $ or spaces", database) });
+    }
+    if prefix.contains(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-') {
+        return Err(ConfigError::Invalid { key: mongo.key("prefix"), message: format!("'{}' can only contain letters, digits, '_' and '-'", prefix) });
+    }
+
+    Ok(Mongo { url, database, prefix })
+}
+
+fn get_storage(toml: &toml::Value) -> Result<Storage, ConfigError> {
+    let storage = Section::optional(toml, "storage")?;
+    let default = Storage::default();
+    let backend = match storage.get::<String>("backend")? {
+        Some(name) => choice(storage.key("backend"), &name, &[("mongo", Backend::Mongo), ("sqlite", Backend::Sqlite)])?,
+        None => default.backend
+    };
+
+    Ok(Storage {
+        backend,
+        path: storage.or("path", default.path)?,
+        refresh: storage.or("refresh", default.refresh)?
+    })
+}
+
+fn get_moderation(toml: &toml::Value) -> Result<Moderation, ConfigError> {
+    let moderation = Section::optional(toml, "moderation")?;
+    let actions = |level: &str| -> Result<Vec<Action>, ConfigError> {
+        match moderation.list(level)? {
+            Some(items) => items.into_iter()
+                .map(|(key, item)| {
+                    let name: String = read(key.clone(), item)?;
+                    choice(key, &name, &Action::all().map(|action| (action.name(), action)))
+                })
+                .collect(),
+            None => Ok(vec![Action::Reply])
+        }
+    };
+
+    Ok(Moderation {
+        keyword: actions("keyword")?,
+        account: actions("account")?
+    })
+}
+
+fn get_report(toml: &toml::Value) -> Result<Report, ConfigError> {
+    let report = Section::optional(toml, "report")?;
+    let default = Report::default();
+    Ok(Report {
+        enable: report.or("enable", default.enable)?,
+        daily_budget: report.or("daily-budget", default.daily_budget)?,
+        keyword: report.or("keyword", default.keyword)?
+    })
+}
+
+fn get_private(toml: &toml::Value) -> Result<Private, ConfigError> {
+    let private = Section::optional(toml, "private")?;
+    Ok(Private {
+        block: private.or("block", false)?,
+        archive: private.or("archive", false)?,
+        reply: private.or("reply", false)?,
+        flag: private.or("flag", false)?
+    })
+}
+
+fn get_renames(toml: &toml::Value) -> Result<Renames, ConfigError> {
+    let renames = Section::optional(toml, "renames")?;
+    let default = Renames::default();
+    Ok(Renames {
+        max: renames.or("max", default.max)?,
+        period: renames.or("period", default.period)?
+    })
+}
+
+fn get_stats(toml: &toml::Value) -> Result<Stats, ConfigError> {
+    let stats = Section::optional(toml, "stats")?;
+    Ok(Stats {
+        period: stats.or("period", Stats::default().period)?
+    })
+}
+
+fn get_retention(toml: &toml::Value) -> Result<Retention, ConfigError> {
+    let retention = Section::optional(toml, "retention")?;
+    let default = Retention::default();
+    let rules = retention.sections("rules")?.iter()
+        .map(|rule| {
+            let collection: String = rule.require("collection")?;
+            let class = match rule.get::<String>("class")? {
+                // Only messages are classed, by their `trigger`
+                Some(_) if collection != "messages" => return Err(ConfigError::Invalid {
+                    key: rule.key("class"),
+                    message: format!("only messages have a class, not '{}'", collection)
+                }),
+                Some(name) => Some(choice(rule.key("class"), &name, &[("scam", MessageClass::Scam), ("clean", MessageClass::Clean)])?),
+                None => None
+            };
+            Ok(RetentionRule {
+                collection,
+                class,
+                field: rule.get("field")?,
+                days: rule.require("days")?
+            })
         })
-        .unwrap()
+        .collect::<Result<Vec<RetentionRule>, ConfigError>>()?;
+
+    Ok(Retention {
+        dry_run: retention.or("dry-run", default.dry_run)?,
+        period: retention.or("period", default.period)?,
+        rules
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -230,6 +461,8 @@ pub struct Log {
     pub type_: LogType,
     pub path: Option<String>,
     pub level: i64,
+    // true : phone numbers and message bodies are logged, they're redacted otherwise
+    pub sensitive: bool
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
@@ -247,10 +480,253 @@ pub struct Sender {
     // Maximum amount of seconds to wait before sending message
     pub max_wait: f64,
     // We don't answer to messages older than this timeout in seconds
-    pub timeout: f64
+    pub timeout: f64,
+    // Our warnings are deleted after this amount of seconds, 0 to keep them
+    pub warning_lifetime: f64
 }
 
 #[derive(Debug, Clone)]
 pub struct Mongo {
-    pub url: String
+    pub url: String,
+    // Database of the records, one per deployment sharing a server
+    pub database: String,
+    // Prepended to the collection names, e.g. "staging_" for "staging_users"
+    pub prefix: String
+}
+
+impl Default for Mongo {
+    fn default() -> Self {
+        Mongo {
+            url: String::new(),
+            database: String::from("fetish"),
+            prefix: String::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    // MongoDB server at the `[mongo]` url
+    Mongo,
+    // SQLite file, only available when built with the `sqlite` feature
+    Sqlite
+}
+
+#[derive(Debug, Clone)]
+pub struct Storage {
+    // Where the records are stored
+    pub backend: Backend,
+    // Path of the SQLite database file
+    pub path: String,
+    // Seconds between two reloads of the keywords and forbidden names, when changes can't be watched
+    pub refresh: u64
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage {
+            backend: Backend::Mongo,
+            path: String::from("fetish.db"),
+            refresh: 10
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // Reply to the scam message with a warning
+    Reply,
+    // Delete the scam message
+    Delete,
+    // Restrict the sender so they can't send messages anymore
+    Restrict,
+    // Ban the sender from the chat
+    Ban,
+    // Delete all of the sender's messages in the chat
+    DeleteAll
+}
+
+impl Action {
+
+    pub fn all() -> [Action; 5] {
+        [Action::Reply, Action::Delete, Action::Restrict, Action::Ban, Action::DeleteAll]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Reply => "reply",
+            Action::Delete => "delete",
+            Action::Restrict => "restrict",
+            Action::Ban => "ban",
+            Action::DeleteAll => "delete-all"
+        }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Moderation {
+    // Actions taken when the message matches keywords
+    pub keyword: Vec<Action>,
+    // Actions taken when the sender is a known scammer
+    pub account: Vec<Action>
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Moderation {
+            keyword: vec![Action::Reply],
+            account: vec![Action::Reply]
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    // true : report scams to Telegram, false : don't report anything
+    pub enable: bool,
+    // Maximum amount of reports sent per day
+    pub daily_budget: u64,
+    // Also report messages only matching keywords, not only the ones from known scammers
+    pub keyword: bool
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report {
+            enable: false,
+            daily_budget: 20,
+            keyword: false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Private {
+    // Block the scammer writing to us
+    pub block: bool,
+    // Move the chat with the scammer to the archive
+    pub archive: bool,
+    // Answer the scammer with the scammer account text
+    pub reply: bool,
+    // Flag the scammer as `scam` in DB, so their messages are sanctioned in every group
+    pub flag: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct Renames {
+    // Users changing their name or username more than this within `period` are flagged, 0 to disable
+    pub max: u64,
+    // Seconds
+    pub period: u64
+}
+
+impl Default for Renames {
+    fn default() -> Self {
+        Renames {
+            max: 3,
+            period: 7 * 24 * 3600
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    // Seconds between two computations of the daily rollups
+    pub period: u64
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            period: 600
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageClass {
+    // Messages that triggered a sanction
+    Scam,
+    // Every other message
+    Clean
+}
+
+#[derive(Debug, Clone)]
+pub struct RetentionRule {
+    // Collection the rule applies to, its documents must have a `date`
+    pub collection: String,
+    // Only the messages of this class, every document of the collection otherwise
+    pub class: Option<MessageClass>,
+    // Only this field is removed, the documents are kept for the counters, the whole documents are deleted otherwise
+    pub field: Option<String>,
+    // Age in days from which the documents are purged
+    pub days: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct Retention {
+    // true : only log what would be purged
+    pub dry_run: bool,
+    // Seconds between two applications of the rules
+    pub period: u64,
+    pub rules: Vec<RetentionRule>
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            dry_run: false,
+            period: 3600,
+            rules: Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Action, Backend, Config, ConfigError};
+
+    static PATHS: &str = r#"
+        [paths]
+        keywords = "keywords.json"
+        message = "message.txt"
+        scammer-account = "scammer_account.txt"
+        about = "about.txt"
+        session = "tdlib"
+        [storage]
+        backend = "sqlite"
+    "#;
+
+    fn config(sections: &str) -> Result<Config, ConfigError> {
+        Config::new(format!("{}\n{}", PATHS, sections))
+    }
+
+    #[test]
+    fn errors_name_the_key_and_the_expected_type() {
+        let sender = "[sender]\nsend = false\nmax-wait = 8.0\ntimeout = 300.0\n";
+
+        assert_eq!(config(&format!("{}min-wait = 3", sender)).err(), Some(ConfigError::WrongType { key: String::from("sender.min-wait"), expected: "float", found: "integer" }));
+        assert_eq!(config(sender).err(), Some(ConfigError::Missing { key: String::from("sender.min-wait") }));
+        assert!(config(&format!("{}min-wait = 3", sender)).err().unwrap().to_string().contains("3.0"));
+        assert!(matches!(Config::new("[paths"), Err(ConfigError::Syntax { .. })));
+    }
+
+    #[test]
+    fn list_items_are_checked_one_by_one() {
+        let sender = "[sender]\nsend = false\nmin-wait = 3.0\nmax-wait = 8.0\ntimeout = 300.0\n";
+        let loaded = config(&format!("{}[moderation]\nkeyword = [\"reply\", \"delete\"]\n", sender)).unwrap();
+
+        assert_eq!(loaded.moderation.keyword, vec![Action::Reply, Action::Delete]);
+        assert_eq!(loaded.moderation.account, vec![Action::Reply]);
+        // SQLite storage doesn't need the mongo url nor its environment variables
+        assert_eq!(loaded.storage.backend, Backend::Sqlite);
+        assert!(matches!(config(&format!("{}[moderation]\nkeyword = [\"reply\", \"kick\"]\n", sender)),
+            Err(ConfigError::Invalid { key, .. }) if key == "moderation.keyword[1]"));
+        assert!(matches!(config(&format!("{}[[retention.rules]]\ncollection = \"messages\"\ndays = -1\n", sender)),
+            Err(ConfigError::WrongType { key, .. }) if key == "retention.rules[0].days"));
+        assert!(matches!(config(&format!("{}[[retention.rules]]\ncollection = \"stats\"\nclass = \"scam\"\ndays = 30\n", sender)),
+            Err(ConfigError::Invalid { key, .. }) if key == "retention.rules[0].class"));
+    }
+
 }
@@ -0,0 +1,35 @@
+use std::fmt::{Display, Formatter};
+
+/**
+*   Why a config file can't be loaded, with the path of the key at fault, e.g. `sender.min-wait`
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    // The file can't be read
+    File { path: String, message: String },
+    // Not valid TOML
+    Syntax { message: String },
+    Missing { key: String },
+    WrongType { key: String, expected: &'static str, found: &'static str },
+    // A value out of the accepted ones
+    Invalid { key: String, message: String },
+    // Environment variable the value of a key refers to
+    Env { key: String, variable: String }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::File { path, message } => write!(f, "Can't read config file '{}' : {}", path, message),
+            ConfigError::Syntax { message } => write!(f, "Config file isn't valid TOML : {}", message),
+            ConfigError::Missing { key } => write!(f, "Missing '{}'", key),
+            // TOML floats need their decimal part
+            ConfigError::WrongType { key, expected: "float", found: "integer" } => write!(f, "'{}' should be a float, found integer, write 3.0 instead of 3", key),
+            ConfigError::WrongType { key, expected, found } => write!(f, "'{}' should be a {}, found {}", key, expected, found),
+            ConfigError::Invalid { key, message } => write!(f, "Invalid '{}' : {}", key, message),
+            ConfigError::Env { key, variable } => write!(f, "'{}' needs the {} environment variable", key, variable)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -1,6 +1,19 @@
 mod config;
+mod error;
 
 pub use config::Config;
+pub use error::ConfigError;
 pub use config::Sender;
 pub use config::Log;
-pub use config::LogType;
\ No newline at end of file
+pub use config::LogType;
+pub use config::Storage;
+pub use config::Backend;
+pub use config::Moderation;
+pub use config::Action;
+pub use config::Report;
+pub use config::Private;
+pub use config::Renames;
+pub use config::Stats;
+pub use config::Retention;
+pub use config::RetentionRule;
+pub use config::MessageClass;
@@ -0,0 +1,183 @@
+use mongodb::bson::Bson;
+use model::{Audit, ForbiddenNames, Keywords, Record, User};
+use crate::recorder::Recorder;
+
+// User fields an admin can set
+pub static USER_FLAGS: [&str; 2] = ["scam", "bypass"];
+
+/**
+*   Who makes a change and why, required by every audited change
+*/
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub author: String,
+    pub reason: String
+}
+
+impl Signature {
+
+    // The author defaults to the system user
+    pub fn new(author: Option<&str>, reason: Option<&str>) -> Result<Self, String> {
+        let author = author.map(str::to_string)
+            .or_else(|| std::env::var("USER").ok())
+            .filter(|author| !author.trim().is_empty())
+            .ok_or_else(|| String::from("Missing --author"))?;
+        let reason = reason.map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .ok_or_else(|| String::from("Missing --reason, every change is audited with its reason"))?;
+        Ok(Signature {
+            author,
+            reason: reason.to_string()
+        })
+    }
+
+}
+
+/**
+*   Sets a field, or a whole document with `WHOLE_DOCUMENT`, and audits the change.
+*   Returns the audit entry, none when the value was already set.
+*/
+pub async fn change<R: Recorder>(recorder: &R, collection: &str, target: i64, field: &str, new: Bson, signature: &Signature) -> Result<Option<Audit>, String> {
+    let old = current(recorder, collection, target, field).await?;
+    if old == new {
+        return Ok(None);
+    }
+    let audit = Audit::new(&signature.author, &signature.reason, collection, target, field, old, new);
+    apply(recorder, &audit).await?;
+    Ok(Some(audit))
+}
+
+/**
+*   Restores the old value of an audited change, unless the value changed again since
+*/
+pub async fn revert<R: Recorder>(recorder: &R, id: i64, signature: &Signature) -> Result<Audit, String> {
+    let audit = recorder.get::<Audit>(id).await.map_err(|e| format!("Failed to get change '{}' : {:?}", id, e))?
+        .ok_or_else(|| format!("Change '{}' not found", id))?;
+    let current = current(recorder, audit.target_collection(), audit.target(), audit.field()).await?;
+    if current != *audit.new_value() {
+        return Err(format!("'{}' of [{}] changed since change '{}', revert the later changes first", field_name(&audit), audit.target(), id));
+    }
+
+    let revert = audit.revert(&signature.author, &signature.reason);
+    apply(recorder, &revert).await?;
+    Ok(revert)
+}
+
+// Name of the changed field, or of the collection for a whole document
+pub fn field_name(audit: &Audit) -> &str {
+    match audit.field() {
+        "" => audit.target_collection(),
+        field => field
+    }
+}
+
+async fn current<R: Recorder>(recorder: &R, collection: &str, target: i64, field: &str) -> Result<Bson, String> {
+    match (collection, field) {
+        ("users", "scam") => Ok(Bson::Boolean(user(recorder, target).await?.is_some_and(|user| user.is_scam_by_admin()))),
+        ("users", "bypass") => Ok(Bson::Boolean(user(recorder, target).await?.is_some_and(|user| user.is_bypass()))),
+        ("config", "") if target == model::KEYWORDS_ID => document::<R, Keywords>(recorder, target).await,
+        ("config", "") if target == model::FORBIDDEN_NAMES_ID => document::<R, ForbiddenNames>(recorder, target).await,
+        _ => Err(unaudited(collection, target, field))
+    }
+}
+
+// Saves the new value of a change, then its audit entry
+async fn apply<R: Recorder>(recorder: &R, audit: &Audit) -> Result<(), String> {
+    let (collection, target, field) = (audit.target_collection(), audit.target(), audit.field());
+    match (collection, field, audit.new_value()) {
+        ("users", _, Bson::Boolean(value)) if USER_FLAGS.contains(&field) => {
+            // A user we never saw is created, so the flag applies as soon as they show up
+            let mut user = user(recorder, target).await?.unwrap_or_else(|| User::unknown(target));
+            match field {
+                "scam" => user.scam = *value,
+                _ => { user.set_bypass(*value); }
+            }
+            save(recorder, &user).await?;
+        },
+        ("config", "", value) if target == model::KEYWORDS_ID => set_document::<R, Keywords>(recorder, target, value).await?,
+        ("config", "", value) if target == model::FORBIDDEN_NAMES_ID => set_document::<R, ForbiddenNames>(recorder, target, value).await?,
+        _ => return Err(unaudited(collection, target, field))
+    }
+    save(recorder, audit).await
+}
+
+async fn user<R: Recorder>(recorder: &R, id: i64) -> Result<Option<User>, String> {
+    recorder.get::<User>(id).await.map_err(|e| format!("Failed to get user '{}' : {:?}", id, e))
+}
+
+async fn document<R: Recorder, T: Record>(recorder: &R, id: i64) -> Result<Bson, String> {
+    let record = recorder.get::<T>(id).await.map_err(|e| format!("Failed to get [{}] from '{}' : {:?}", id, T::collection(), e))?;
+    Ok(record.map_or(Bson::Null, |record| Bson::Document(record.to_doc())))
+}
+
+// A null value deletes the document, it didn't exist before the change
+async fn set_document<R: Recorder, T: Record>(recorder: &R, id: i64, value: &Bson) -> Result<(), String> {
+    match value {
+        Bson::Document(doc) => save(recorder, &T::from_doc(doc).map_err(|e| e.to_string())?).await,
+        Bson::Null => recorder.delete::<T>(id).await.map(|_| ())
+            .map_err(|e| format!("Failed to delete [{}] from '{}' : {:?}", id, T::collection(), e)),
+        other => Err(format!("Expected a document or null, got {}", other))
+    }
+}
+
+async fn save<R: Recorder>(recorder: &R, record: &impl Record) -> Result<(), String> {
+    recorder.save(record).await.map_err(|e| format!("Failed to save [{}] in '{}' : {:?}", record.id(), record.collection_name(), e))
+}
+
+fn unaudited(collection: &str, target: i64, field: &str) -> String {
+    format!("'{}' of [{}] in '{}' isn't an audited field", field, target, collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{Bson, doc};
+    use model::{Audit, Keywords, Record, User};
+    use crate::audit::{change, revert, Signature};
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+
+    fn signature(author: &str) -> Signature {
+        Signature::new(Some(author), Some("Reported by members")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn user_flags_are_audited_and_reverted() {
+        let memory = Memory::new();
+        memory.save(&User::from_doc(&doc! { "id": 42_i64, "first_name": "Julie" }).unwrap()).await.unwrap();
+
+        let audit = change(&memory, "users", 42, "scam", Bson::Boolean(true), &signature("alice")).await.unwrap().unwrap();
+        assert!(memory.get::<User>(42).await.unwrap().unwrap().is_scam_by_admin());
+        assert_eq!((audit.old(), audit.author()), (&Bson::Boolean(false), "alice"));
+        // Setting it again changes nothing
+        assert!(change(&memory, "users", 42, "scam", Bson::Boolean(true), &signature("alice")).await.unwrap().is_none());
+
+        let reverted = revert(&memory, audit.id(), &signature("bob")).await.unwrap();
+        assert!(!memory.get::<User>(42).await.unwrap().unwrap().is_scam_by_admin());
+        assert_eq!(reverted.reverts(), Some(audit.id()));
+        assert_eq!(memory.get::<User>(42).await.unwrap().unwrap().first_name, "Julie");
+        // Reverting twice would override the revert
+        assert!(revert(&memory, audit.id(), &signature("bob")).await.is_err());
+        assert_eq!(memory.count::<Audit>(Filter::eq("target", 42_i64)).await.unwrap(), 2);
+
+        // Users never seen are created with only the flag set
+        change(&memory, "users", 7, "bypass", Bson::Boolean(true), &signature("alice")).await.unwrap();
+        let unknown = memory.get::<User>(7).await.unwrap().unwrap();
+        assert!(unknown.is_bypass() && !unknown.is_scam_by_admin());
+    }
+
+    #[tokio::test]
+    async fn reverting_a_first_sync_deletes_the_document() {
+        let memory = Memory::new();
+        let keywords = Bson::Document(Keywords::new(vec![String::from("dispo")], vec![], vec![]).to_doc());
+
+        let audit = change(&memory, "config", model::KEYWORDS_ID, "", keywords, &signature("alice")).await.unwrap().unwrap();
+        assert_eq!(audit.old(), &Bson::Null);
+        assert!(memory.get::<Keywords>(model::KEYWORDS_ID).await.unwrap().is_some());
+
+        revert(&memory, audit.id(), &signature("alice")).await.unwrap();
+        assert!(memory.get::<Keywords>(model::KEYWORDS_ID).await.unwrap().is_none());
+        assert!(change(&memory, "users", 42, "first_name", Bson::String(String::from("Bob")), &signature("alice")).await.is_err());
+        assert!(Signature::new(Some("alice"), Some("  ")).is_err());
+    }
+
+}
@@ -0,0 +1,109 @@
+use log::{info, warn, error};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use futures::stream::TryStreamExt;
+use model::{ForbiddenNames, Keywords, Record};
+use crate::recorder::Recorder;
+use crate::storage::Storage;
+
+/**
+*   Keywords and forbidden names kept in memory, so analysing a message doesn't cost any query.
+*   They're reloaded whenever the config collection changes, or every `[storage] refresh` seconds
+*   when MongoDB can't stream the changes.
+*/
+#[derive(Clone, Default)]
+pub struct Cache {
+    keywords: Arc<RwLock<Option<Arc<Keywords>>>>,
+    forbidden_names: Arc<RwLock<Option<Arc<ForbiddenNames>>>>
+}
+
+impl Cache {
+
+    pub fn new() -> Self {
+        Cache::default()
+    }
+
+    pub fn keywords(&self) -> Option<Arc<Keywords>> {
+        self.keywords.read().unwrap().clone()
+    }
+
+    pub fn forbidden_names(&self) -> Option<Arc<ForbiddenNames>> {
+        self.forbidden_names.read().unwrap().clone()
+    }
+
+    pub async fn load<R: Recorder>(&self, recorder: &R) {
+        if let Some(keywords) = fetch::<Keywords, R>(recorder, model::KEYWORDS_ID).await {
+            *self.keywords.write().unwrap() = keywords.map(Arc::new);
+        }
+        if let Some(forbidden_names) = fetch::<ForbiddenNames, R>(recorder, model::FORBIDDEN_NAMES_ID).await {
+            *self.forbidden_names.write().unwrap() = forbidden_names.map(Arc::new);
+        }
+    }
+
+    /**
+    *   Keeps the cache up to date, never returns
+    */
+    pub async fn follow(self, storage: Storage, refresh: Duration) {
+        if let Some(mongo) = storage.as_mongo() {
+            match mongo.watch(model::CONFIG_COLLECTION).await {
+                Ok(mut changes) => {
+                    info!("Watching the changes of '{}'", model::CONFIG_COLLECTION);
+                    loop {
+                        match changes.try_next().await {
+                            Ok(Some(_)) => self.load(&storage).await,
+                            Ok(None) => break,
+                            Err(e) => {
+                                error!("Lost the changes of '{}' : {:?}", model::CONFIG_COLLECTION, e);
+                                break;
+                            }
+                        }
+                    }
+                },
+                Err(e) => warn!("Can't watch the changes of '{}', reloading every {} seconds : {:?}", model::CONFIG_COLLECTION, refresh.as_secs(), e)
+            }
+        }
+
+        loop {
+            tokio::time::sleep(refresh).await;
+            self.load(&storage).await;
+        }
+    }
+
+}
+
+// None when the document can't be read, so the cached one is kept
+async fn fetch<T: Record, R: Recorder>(recorder: &R, id: i64) -> Option<Option<T>> {
+    match recorder.get::<T>(id).await {
+        Ok(record) => Some(record),
+        Err(e) => {
+            error!("Failed to read [{}] from '{}' : {:?}", id, T::collection(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{ForbiddenNames, Keywords, KeywordMatch};
+    use crate::cache::Cache;
+    use crate::memory::Memory;
+    use crate::recorder::Recorder;
+
+    #[tokio::test]
+    async fn edits_are_seen_once_reloaded() {
+        let memory = Memory::new();
+        let cache = Cache::new();
+        memory.save(&ForbiddenNames::new(vec![String::from("Mamadou")])).await.unwrap();
+        cache.load(&memory).await;
+
+        assert!(cache.keywords().is_none());
+        assert!(cache.forbidden_names().unwrap().name_match("MAMADOU", "DIALLO"));
+
+        memory.save(&Keywords::new(vec![String::from("whatsapp")], vec![], vec![])).await.unwrap();
+        assert!(cache.keywords().is_none());
+
+        cache.load(&memory).await;
+        assert!(matches!(cache.keywords().unwrap().text_match("ECRIS MOI SUR WHATSAPP"), KeywordMatch::FrMatch));
+    }
+
+}
@@ -0,0 +1,84 @@
+use log::{info, error};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use config::Config;
+use rtdlib::types::DeleteMessages;
+use telegram_client::api::aevent::EventApi;
+use model::{Record, Warning};
+use crate::storage::Storage;
+use crate::recorder::{Filter, Recorder};
+
+// Seconds between two looks for expired warnings
+static PERIOD: u64 = 60;
+
+/**
+*   Deletes our warnings once they expire, or once the scam message they answer is deleted
+*/
+#[derive(Clone)]
+pub struct Cleaner {
+    config: Config,
+    api: EventApi,
+    storage: Storage
+}
+
+impl Cleaner {
+
+    pub fn new(config: Config, api: EventApi, storage: Storage) -> Self {
+        Cleaner {
+            config,
+            api,
+            storage
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+            self.delete_warnings(Filter::gt("expire_date", 0).and(Filter::lte("expire_date", now))).await;
+            tokio::time::sleep(Duration::from_secs(PERIOD)).await;
+        }
+    }
+
+    pub async fn on_messages_deleted(&self, chat_id: i64, message_ids: &Vec<i64>) {
+        // Warnings answering deleted scam messages
+        self.delete_warnings(Filter::eq("chat_id", chat_id).and(Filter::is_in("reply_to", message_ids.clone()))).await;
+
+        // Warnings deleted by someone else
+        match self.storage.find::<Warning>(Filter::eq("chat_id", chat_id).and(Filter::is_in("id", message_ids.clone()))).await {
+            Ok(warnings) => for warning in warnings.iter() {
+                self.forget(warning).await;
+            },
+            Err(e) => error!("Failed to get warnings from DB : {:?}", e)
+        }
+    }
+
+    async fn delete_warnings(&self, filter: Filter) {
+        match self.storage.find::<Warning>(filter).await {
+            Ok(warnings) => for warning in warnings.iter() {
+                self.delete(warning).await;
+            },
+            Err(e) => error!("Failed to get warnings from DB : {:?}", e)
+        }
+    }
+
+    async fn delete(&self, warning: &Warning) {
+        if self.config.sender.send {
+            info!("Deleting warning [{}] in chat [{}]", warning.id(), warning.chat_id());
+            if let Err(e) = self.api.delete_messages(DeleteMessages::builder()
+                .chat_id(warning.chat_id())
+                .message_ids(vec![warning.id()])
+                .revoke(true)
+                .build()) {
+                error!("Failed to delete warning [{}] : {:?}", warning.id(), e);
+                return;
+            }
+        }
+        self.forget(warning).await;
+    }
+
+    async fn forget(&self, warning: &Warning) {
+        if let Err(e) = self.storage.delete::<Warning>(warning.key()).await {
+            error!("Failed to delete warning [{}] from DB : {:?}", warning.id(), e);
+        }
+    }
+
+}
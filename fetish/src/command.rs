@@ -0,0 +1,535 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::warn;
+use mongodb::bson::Bson;
+use config::{Backend, Config};
+use model::{Audit, Chat, Counts, Dimension, Record, Rollup, User};
+use crate::audit::{self, Signature};
+use crate::recorder::{Filter, Recorder};
+use crate::retention;
+use crate::storage::Storage;
+use crate::transfer::{self, Format};
+use crate::sync::{self, Diff};
+
+static USAGE: &str = "Usage : fetish <config> [command]
+Without a command, runs the bot. Commands :
+    check-config
+    export <collection> <file> [--format jsonl|csv] [--since YYYY-MM-DD] [--until YYYY-MM-DD] [--flag FIELD]...
+    import <collection> <file> [--format jsonl|csv]
+    keywords sync [file] [--language fr|en|de] [--dry-run] --reason TEXT [--author NAME]
+    forbidden-names sync <file> [--dry-run] --reason TEXT [--author NAME]
+    user <id>
+    user <id> set scam|bypass true|false --reason TEXT [--author NAME]
+    chats [--protected]
+    stats [--since YYYY-MM-DD] [--until YYYY-MM-DD] [--by chat|scam-type|keyword|language]
+    audit [--since YYYY-MM-DD] [--until YYYY-MM-DD] [--collection users|config] [--target ID] [--author NAME]
+    audit revert <id> --reason TEXT [--author NAME]
+Collections : users, messages, chats, stats, rollups, audit, config
+Changes are recorded in the audit with their reason, and their author, the system user by default.
+Stats are summed from the daily rollups, over the last 7 days by default.
+Keywords files are a flat list in --language, French by default, or an object with 'fr', 'en' and 'de' lists.
+Without a file, the keywords are read from the 'keywords' path of the config.";
+
+// Options without a value
+static SWITCHES: [&str; 2] = ["dry-run", "protected"];
+
+/**
+*   Arguments of a command, the positional ones and the `--name value` options
+*/
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>
+}
+
+impl Args {
+
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (mut positional, mut options) = (Vec::new(), Vec::new());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if SWITCHES.contains(&name) => options.push((name.to_string(), String::from("true"))),
+                Some(name) => match args.next() {
+                    Some(value) => options.push((name.to_string(), value.clone())),
+                    None => return Err(format!("Missing value of --{}", name))
+                },
+                None => positional.push(arg.clone())
+            }
+        }
+        Ok(Args { positional, options })
+    }
+
+    fn get(&self, index: usize) -> Result<&str, String> {
+        self.positional.get(index).map(String::as_str).ok_or_else(|| String::from(USAGE))
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+
+    fn all(&self, name: &str) -> Vec<&str> {
+        self.options.iter().filter(|(option, _)| option == name).map(|(_, value)| value.as_str()).collect()
+    }
+
+    // Only the options a command knows are accepted, so a typo doesn't widen an export
+    fn only(&self, names: &[&str]) -> Result<(), String> {
+        match self.options.iter().find(|(option, _)| !names.contains(&option.as_str())) {
+            Some((option, _)) => Err(format!("Unknown option --{}\n{}", option, USAGE)),
+            None => Ok(())
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.option(name).is_some()
+    }
+
+    fn format(&self, path: &str) -> Result<Format, String> {
+        self.option("format").map_or(Ok(Format::from_path(path)), Format::from)
+    }
+
+}
+
+/**
+*   Runs a command instead of the bot : `fetish <config> <command> ...`
+*/
+pub async fn run(conf_path: &str, args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let config = Config::from(conf_path).map_err(|e| format!("Invalid config file '{}' : {}", conf_path, e))?;
+    // Checked before the encryption key is set, so a wrong key is listed with the other problems
+    if args.get(0)? == "check-config" {
+        return check_config(conf_path, &config, &args);
+    }
+    if !crate::fetish::init_cipher().map_err(|e| e.to_string())? {
+        warn!("{} isn't set, phone numbers and message contents are stored in clear", model::cipher::KEY_VAR);
+    }
+
+    match args.get(0)? {
+        "export" => export(&open(config).await?, &args).await,
+        "import" => import(&open(config).await?, &args).await,
+        "keywords" if args.get(1)? == "sync" => sync_keywords(&open(config.clone()).await?, &config, &args).await,
+        "forbidden-names" if args.get(1)? == "sync" => sync_forbidden_names(&open(config).await?, &args).await,
+        "user" if args.positional.get(2).map(String::as_str) == Some("set") => set_user(&open(config).await?, &args).await,
+        "user" => user(&open(config).await?, &args).await,
+        "chats" => chats(&open(config).await?, &args).await,
+        "stats" => stats(&open(config).await?, &args).await,
+        "audit" if args.positional.get(1).map(String::as_str) == Some("revert") => revert(&open(config).await?, &args).await,
+        "audit" => audit(&open(config).await?, &args).await,
+        "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        other => Err(format!("Unknown command '{}'\n{}", other, USAGE))
+    }
+}
+
+async fn open(config: Config) -> Result<Storage, String> {
+    Storage::new(config).await.map_err(|e| format!("Can't open the storage : {:?}", e))
+}
+
+async fn export(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["format", "since", "until", "flag"])?;
+    let (collection, path) = (args.get(1)?, args.get(2)?);
+
+    let mut filter = Filter::All;
+    if args.option("since").is_some() || args.option("until").is_some() {
+        let date_field = transfer::date_field(collection)
+            .ok_or_else(|| format!("'{}' documents aren't dated, --since and --until can't apply to them", collection))?;
+        if let Some(since) = args.option("since") {
+            filter = filter.and(Filter::gte(date_field, timestamp(since)?));
+        }
+        if let Some(until) = args.option("until") {
+            // The whole day is included
+            filter = filter.and(Filter::lt(date_field, timestamp(until)? + 24 * 3600));
+        }
+    }
+    for flag in args.all("flag") {
+        filter = filter.and(Filter::eq(flag, true));
+    }
+
+    let file = File::create(path).map_err(|e| format!("Can't create '{}' : {}", path, e))?;
+    let count = transfer::export(storage, collection, filter, args.format(path)?, file).await?;
+    println!("Exported {} '{}' documents to '{}'", count, collection, path);
+    Ok(())
+}
+
+async fn import(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["format"])?;
+    let (collection, path) = (args.get(1)?, args.get(2)?);
+
+    let file = File::open(path).map_err(|e| format!("Can't open '{}' : {}", path, e))?;
+    let count = transfer::import(storage, collection, args.format(path)?, BufReader::new(file)).await?;
+    println!("Imported {} '{}' documents from '{}'", count, collection, path);
+    Ok(())
+}
+
+async fn sync_keywords(storage: &Storage, config: &Config, args: &Args) -> Result<(), String> {
+    args.only(&["language", "dry-run", "reason", "author"])?;
+    let path = args.positional.get(2).map_or(config.keywords_path.as_str(), String::as_str);
+    let signature = sync_signature(args)?;
+
+    let keywords = sync::parse_keywords(&read(path)?, args.option("language").unwrap_or("fr"))?;
+    let diffs = sync::sync_keywords(storage, keywords, signature.as_ref()).await?;
+    print_diffs(&diffs, args.switch("dry-run"));
+    Ok(())
+}
+
+async fn sync_forbidden_names(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["dry-run", "reason", "author"])?;
+    let path = args.get(2)?;
+    let signature = sync_signature(args)?;
+
+    let names = sync::parse_forbidden_names(&read(path)?)?;
+    let diffs = sync::sync_forbidden_names(storage, names, signature.as_ref()).await?;
+    print_diffs(&diffs, args.switch("dry-run"));
+    Ok(())
+}
+
+// A dry run changes nothing, so it needs no reason
+fn sync_signature(args: &Args) -> Result<Option<Signature>, String> {
+    match args.switch("dry-run") {
+        true => Ok(None),
+        false => Signature::new(args.option("author"), args.option("reason")).map(Some)
+    }
+}
+
+// Shows a user with the names they had
+async fn user(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&[])?;
+    let id = args.get(1)?;
+    let id = id.parse::<i64>().map_err(|_| format!("'{}' isn't a user id", id))?;
+
+    let user = storage.get::<User>(id).await.map_err(|e| format!("Failed to get user '{}' : {:?}", id, e))?
+        .ok_or_else(|| format!("User '{}' not found", id))?;
+    println!("User {} : {} {} @{}{}{}", id, user.first_name, user.last_name(), user.username(),
+        if user.is_scam_by_admin() { " [scam]" } else { "" },
+        if user.is_bypass() { " [bypass]" } else { "" });
+    for name in user.names().iter().rev() {
+        println!("    until {} : {} {} @{}", date(name.date), name.first_name, name.last_name, name.username);
+    }
+    if user.names().is_empty() {
+        println!("    never renamed");
+    }
+    Ok(())
+}
+
+async fn set_user(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["reason", "author"])?;
+    let (id, flag, value) = (args.get(1)?, args.get(3)?, args.get(4)?);
+    let id = id.parse::<i64>().map_err(|_| format!("'{}' isn't a user id", id))?;
+    if !audit::USER_FLAGS.contains(&flag) {
+        return Err(format!("Unknown flag '{}', expected one of {}", flag, audit::USER_FLAGS.join(", ")));
+    }
+    let value = value.parse::<bool>().map_err(|_| format!("'{}' isn't true or false", value))?;
+    let signature = Signature::new(args.option("author"), args.option("reason"))?;
+
+    match audit::change(storage, model::USERS_COLLECTION, id, flag, Bson::Boolean(value), &signature).await? {
+        Some(change) => println!("User {} : '{}' set to {}, change {}", id, flag, value, change.id()),
+        None => println!("User {} : '{}' already {}", id, flag, value)
+    }
+    Ok(())
+}
+
+// Lists the chats we're in, with what we did there
+async fn chats(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["protected"])?;
+    let chats = storage.find::<Chat>(Filter::All).await.map_err(|e| format!("Failed to get chats : {:?}", e))?;
+
+    for chat in chats.iter().filter(|chat| !args.switch("protected") || chat.is_protected()) {
+        println!("{} '{}' : {}{}, {} members, {} scams, {} warnings, last active {}", chat.id(), chat.title(),
+            if chat.status().is_empty() { "unknown status" } else { chat.status() },
+            if chat.is_protected() { " [protected]" } else { "" },
+            chat.member_count(), chat.scam_count(), chat.warning_count(),
+            if chat.last_activity() > 0 { date(chat.last_activity()) } else { String::from("never") });
+    }
+    Ok(())
+}
+
+// Prints a table per dimension, of the rollups summed over the days
+async fn stats(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["since", "until", "by"])?;
+    let today = model::day_of(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64);
+    let since = args.option("since").map_or(Ok(today - 6 * model::DAY_SECONDS), timestamp)?;
+    let until = args.option("until").map_or(Ok(today), timestamp)?;
+    let dimensions = match args.option("by") {
+        Some(name) => vec![Dimension::from(name).ok_or_else(|| format!("Unknown dimension '{}'\n{}", name, USAGE))?],
+        None => Dimension::all().to_vec()
+    };
+
+    let rollups = storage.find::<Rollup>(Filter::gte("day", since).and(Filter::lte("day", until))).await
+        .map_err(|e| format!("Failed to get rollups : {:?}", e))?;
+    println!("From {} to {}, {} rollups", &date(since)[..10], &date(until)[..10], rollups.len());
+
+    for dimension in dimensions {
+        println!("\n{:<40}{:>10}{:>10}{:>10}{:>10}{:>12}", dimension.name(), "messages", "threats", "scams", "sent", "suppressed");
+        for (value, counts) in totals(&rollups, dimension) {
+            let label = match dimension {
+                Dimension::Chat => chat_label(storage, &value).await,
+                _ => value
+            };
+            println!("{:<40}{:>10}{:>10}{:>10}{:>10}{:>12}", label, counts.messages, counts.threats, counts.scams, counts.warnings_sent, counts.warnings_suppressed);
+        }
+    }
+    Ok(())
+}
+
+// Counts of each value of a dimension, most scams first
+fn totals(rollups: &[Rollup], dimension: Dimension) -> Vec<(String, Counts)> {
+    let mut totals: Vec<(String, Counts)> = Vec::new();
+    for rollup in rollups.iter().filter(|rollup| rollup.dimension() == dimension) {
+        match totals.iter_mut().find(|(value, _)| value == rollup.value()) {
+            Some((_, counts)) => { counts.add(rollup.counts()); },
+            None => totals.push((rollup.value().to_string(), *rollup.counts()))
+        }
+    }
+    totals.sort_by(|(a, a_counts), (b, b_counts)| b_counts.scams.cmp(&a_counts.scams).then_with(|| a.cmp(b)));
+    totals
+}
+
+// Lists the audited changes, oldest first
+async fn audit(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["since", "until", "collection", "target", "author"])?;
+    let mut filter = Filter::All;
+    if let Some(since) = args.option("since") {
+        filter = filter.and(Filter::gte("date", timestamp(since)?));
+    }
+    if let Some(until) = args.option("until") {
+        filter = filter.and(Filter::lt("date", timestamp(until)? + 24 * 3600));
+    }
+    if let Some(collection) = args.option("collection") {
+        filter = filter.and(Filter::eq("collection", collection));
+    }
+    if let Some(target) = args.option("target") {
+        filter = filter.and(Filter::eq("target", target.parse::<i64>().map_err(|_| format!("'{}' isn't an id", target))?));
+    }
+    if let Some(author) = args.option("author") {
+        filter = filter.and(Filter::eq("author", author));
+    }
+
+    let mut changes = storage.find::<Audit>(filter).await.map_err(|e| format!("Failed to get the audit : {:?}", e))?;
+    changes.sort_by_key(Audit::id);
+    for change in changes.iter() {
+        println!("{} {} by {} : '{}' of [{}] {} -> {}{}, {}", change.id(), date(change.date()), change.author(),
+            audit::field_name(change), change.target(), summary(change.old()), summary(change.new_value()),
+            change.reverts().map_or_else(String::new, |id| format!(", reverts {}", id)),
+            change.reason());
+    }
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    Ok(())
+}
+
+async fn revert(storage: &Storage, args: &Args) -> Result<(), String> {
+    args.only(&["reason", "author"])?;
+    let id = args.get(2)?;
+    let id = id.parse::<i64>().map_err(|_| format!("'{}' isn't a change id", id))?;
+    let signature = Signature::new(args.option("author"), args.option("reason"))?;
+
+    let change = audit::revert(storage, id, &signature).await?;
+    println!("Change {} reverted by change {}", id, change.id());
+    Ok(())
+}
+
+// Whole documents are too long to print, only their size is
+fn summary(value: &Bson) -> String {
+    match value {
+        Bson::Null => String::from("none"),
+        Bson::Document(doc) => format!("{{{} fields}}", doc.len()),
+        other => other.to_string()
+    }
+}
+
+async fn chat_label(storage: &Storage, chat_id: &str) -> String {
+    let title = match chat_id.parse::<i64>() {
+        Ok(id) => storage.get::<Chat>(id).await.ok().flatten().map(|chat| chat.title().to_string()),
+        Err(_) => None
+    };
+    match title {
+        Some(title) if !title.is_empty() => format!("{} ({})", title.chars().take(24).collect::<String>(), chat_id),
+        _ => chat_id.to_string()
+    }
+}
+
+/**
+*   Checks what the bot needs beside the config file, loaded without errors by then :
+*   the files it reads, the encryption key and the storage, without starting tdlib
+*/
+fn check_config(conf_path: &str, config: &Config, args: &Args) -> Result<(), String> {
+    args.only(&[])?;
+    if std::env::var(model::cipher::KEY_VAR).is_err() {
+        println!("{} isn't set, phone numbers and message contents will be stored in clear", model::cipher::KEY_VAR);
+    }
+
+    let problems = problems(config);
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(format!("{} problems found in '{}'", problems.len(), conf_path));
+    }
+    match config.storage.backend {
+        Backend::Mongo => println!("Config '{}' is valid, records are stored in the '{}' database with the prefix '{}'", conf_path, config.mongo.database, config.mongo.prefix),
+        Backend::Sqlite => println!("Config '{}' is valid, records are stored in '{}'", conf_path, config.storage.path)
+    }
+    Ok(())
+}
+
+// What loading the config can't check : the files it points to, the encryption key, the build's features and values valid only together
+fn problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Err(e) = read(&config.keywords_path).and_then(|json| sync::parse_keywords(&json, "fr")) {
+        problems.push(format!("'paths.keywords' : {}", e));
+    }
+    for (key, path) in [("message", &config.message_path), ("scammer-account", &config.scammer_account_path), ("about", &config.about_path)].iter() {
+        if let Err(e) = read(path) {
+            problems.push(format!("'paths.{}' : {}", key, e));
+        }
+    }
+    if let Err(e) = crate::fetish::init_cipher() {
+        problems.push(e.to_string());
+    }
+    if config.storage.backend == Backend::Sqlite && !cfg!(feature = "sqlite") {
+        problems.push(String::from("'storage.backend' is sqlite, but fetish is built without the 'sqlite' feature"));
+    }
+    if config.sender.min_wait > config.sender.max_wait {
+        problems.push(String::from("'sender.min-wait' is greater than 'sender.max-wait'"));
+    }
+    for (index, rule) in config.retention.rules.iter().enumerate() {
+        if !retention::is_dated(&rule.collection) {
+            problems.push(format!("'retention.rules[{}].collection' : '{}' isn't dated, rules apply to messages, stats and reports", index, rule.collection));
+        }
+        if rule.class.is_some() && rule.collection != model::MESSAGES_COLLECTION {
+            problems.push(format!("'retention.rules[{}].class' : only messages have a class, not '{}'", index, rule.collection));
+        }
+    }
+    problems
+}
+
+fn read(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Can't read '{}' : {}", path, e))
+}
+
+fn print_diffs(diffs: &[Diff], dry_run: bool) {
+    for diff in diffs.iter() {
+        for word in diff.added.iter() {
+            println!("+ [{}] {}", diff.list, word);
+        }
+        for word in diff.removed.iter() {
+            println!("- [{}] {}", diff.list, word);
+        }
+    }
+    match (diffs.iter().all(Diff::is_empty), dry_run) {
+        (true, _) => println!("Already up to date"),
+        (false, true) => println!("Dry run, nothing saved"),
+        (false, false) => println!("Saved")
+    }
+}
+
+/**
+*   Seconds since the epoch of a YYYY-MM-DD day at midnight UTC
+*/
+fn timestamp(date: &str) -> Result<i64, String> {
+    let parts = date.split('-').map(str::parse::<i64>).collect::<Result<Vec<i64>, _>>();
+    match parts.as_deref() {
+        Ok(&[year, month, day]) if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+            // Days from the civil calendar, with years starting in March so leap days come last
+            let year = if month <= 2 { year - 1 } else { year };
+            let era = year.div_euclid(400);
+            let year_of_era = year - era * 400;
+            let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+            let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+            Ok((era * 146_097 + day_of_era - 719_468) * 24 * 3600)
+        },
+        _ => Err(format!("'{}' isn't a YYYY-MM-DD date", date))
+    }
+}
+
+/**
+*   YYYY-MM-DD HH:MM UTC of a timestamp, the inverse of `timestamp`
+*/
+fn date(timestamp: i64) -> String {
+    let (days, seconds) = (timestamp.div_euclid(24 * 3600), timestamp.rem_euclid(24 * 3600));
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{Counts, Dimension, Rollup};
+    use config::{Config, RetentionRule};
+    use crate::command::{date, problems, timestamp, totals, Args};
+
+    #[test]
+    fn dates_are_utc_midnights() {
+        assert_eq!(timestamp("1970-01-01"), Ok(0));
+        assert_eq!(timestamp("2000-03-01"), Ok(951_868_800));
+        assert_eq!(timestamp("2021-12-31"), Ok(1_640_908_800));
+        assert!(timestamp("2021-13-01").is_err());
+        assert!(timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        assert_eq!(date(0), "1970-01-01 00:00");
+        assert_eq!(date(951_868_800 + 3660), "2000-03-01 01:01");
+        assert_eq!(date(timestamp("2024-02-29").unwrap()), "2024-02-29 00:00");
+    }
+
+    #[test]
+    fn config_problems_are_all_reported() {
+        let mut config = Config::empty();
+        config.sender.min_wait = 10.0;
+        config.retention.rules.push(RetentionRule { collection: String::from("users"), class: None, field: None, days: 30 });
+
+        let problems = problems(&config);
+        assert!(problems.iter().any(|problem| problem.starts_with("'paths.keywords'")));
+        assert!(problems.iter().any(|problem| problem.starts_with("'paths.about'")));
+        assert!(problems.iter().any(|problem| problem.starts_with("'sender.min-wait'")));
+        assert!(problems.iter().any(|problem| problem.starts_with("'retention.rules[0].collection'")));
+    }
+
+    #[test]
+    fn totals_are_summed_over_the_days() {
+        let scams = |scams| Counts { scams, ..Counts::default() };
+        let rollups = vec![
+            Rollup::new(0, Dimension::Keyword, "ESCORT", scams(1)),
+            Rollup::new(86_400, Dimension::Keyword, "ESCORT", scams(2)),
+            Rollup::new(0, Dimension::Keyword, "WHATSAPP", scams(4)),
+            Rollup::new(0, Dimension::Language, "fr", scams(7))
+        ];
+        let totals: Vec<(String, i64)> = totals(&rollups, Dimension::Keyword).into_iter().map(|(value, counts)| (value, counts.scams)).collect();
+
+        assert_eq!(totals, vec![(String::from("WHATSAPP"), 4), (String::from("ESCORT"), 3)]);
+    }
+
+    #[test]
+    fn options_are_parsed_apart() {
+        let args: Vec<String> = ["export", "users", "--flag", "scam", "out.csv", "--flag", "bypass"].iter().map(|arg| arg.to_string()).collect();
+        let args = Args::parse(&args).unwrap();
+
+        assert_eq!(args.get(2), Ok("out.csv"));
+        assert_eq!(args.all("flag"), vec!["scam", "bypass"]);
+        assert!(args.only(&["flag"]).is_ok());
+        assert!(args.only(&["format"]).is_err());
+    }
+
+    #[test]
+    fn switches_take_no_value() {
+        let args: Vec<String> = ["keywords", "sync", "--dry-run", "keywords.json"].iter().map(|arg| arg.to_string()).collect();
+        let args = Args::parse(&args).unwrap();
+
+        assert!(args.switch("dry-run"));
+        assert_eq!(args.get(2), Ok("keywords.json"));
+    }
+
+}
@@ -0,0 +1,183 @@
+use log::{info, warn, error};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use regex::Regex;
+use config::Config;
+use model::{ChatUpdate, MessageSent, Record, Warning};
+use crate::storage::Storage;
+use crate::recorder::Recorder;
+
+pub static TOO_MANY_REQUESTS: i64 = 429;
+pub static CHAT_WRITE_FORBIDDEN: &str = "CHAT_WRITE_FORBIDDEN";
+pub static USER_BANNED_IN_CHANNEL: &str = "USER_BANNED_IN_CHANNEL";
+
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    chat_id: i64,
+    // Id of the scam message we reply to, which is also the id of the sanction
+    reply_to: i64,
+    // Date of the scam message
+    date: i64,
+    tracked: Instant
+}
+
+/**
+*   Correlates the warnings we send with the results tdlib gives back for them.
+*
+*   A send request is first known by its `@extra`, then by the temporary id of the message tdlib
+*   answers with, until `updateMessageSendSucceeded` or `updateMessageSendFailed` arrives.
+*   Sends with no outcome after the sender's timeout are forgotten.
+*/
+#[derive(Clone)]
+pub struct Delivery {
+    config: Config,
+    storage: Storage,
+    requests: Arc<Mutex<HashMap<String, Pending>>>,
+    messages: Arc<Mutex<HashMap<i64, Pending>>>,
+    flood_wait: Arc<Mutex<Option<Instant>>>
+}
+
+impl Delivery {
+
+    pub fn new(config: Config, storage: Storage) -> Self {
+        Delivery {
+            config,
+            storage,
+            requests: Arc::new(Mutex::new(HashMap::new())),
+            messages: Arc::new(Mutex::new(HashMap::new())),
+            flood_wait: Arc::new(Mutex::new(None))
+        }
+    }
+
+    pub fn track(&self, extra: String, chat_id: i64, reply_to: i64, date: i64) {
+        self.evict_stale();
+        self.requests.lock().unwrap().insert(extra, Pending { chat_id, reply_to, date, tracked: Instant::now() });
+    }
+
+    // tdlib never told us about these sends, they would stay in memory forever
+    fn evict_stale(&self) {
+        let timeout = Duration::from_secs_f64(self.config.sender.timeout.max(0.0));
+        let fresh = |pending: &Pending| pending.tracked.elapsed() < timeout;
+
+        let mut requests = self.requests.lock().unwrap();
+        let mut messages = self.messages.lock().unwrap();
+        let count = requests.len() + messages.len();
+        requests.retain(|_, pending| fresh(pending));
+        messages.retain(|_, pending| fresh(pending));
+        let evicted = count - requests.len() - messages.len();
+        if evicted > 0 {
+            warn!("No outcome for {} warnings after {} seconds, no longer waiting for them", evicted, self.config.sender.timeout);
+        }
+    }
+
+    // tdlib answered the send request with the temporary message
+    pub fn on_message(&self, extra: &str, message_id: i64) {
+        if let Some(pending) = self.requests.lock().unwrap().remove(extra) {
+            self.messages.lock().unwrap().insert(message_id, pending);
+        }
+    }
+
+    pub async fn on_success(&self, old_message_id: i64, message_id: i64) {
+        let pending = self.messages.lock().unwrap().remove(&old_message_id);
+        if let Some(pending) = pending {
+            info!("Warning for message [{}] delivered as [{}]", pending.reply_to, message_id);
+            let latency = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 - pending.date;
+            self.save(MessageSent::sent(pending.reply_to, pending.chat_id, message_id, latency)).await;
+
+            self.update_chat(ChatUpdate::warning(pending.chat_id)).await;
+
+            // Remember the warning so it can be cleaned up later
+            let warning = Warning::new(message_id, pending.chat_id, pending.reply_to, self.config.sender.warning_lifetime as u64);
+            if let Err(e) = self.storage.save(&warning).await {
+                error!("Failed to save warning [{}] in DB : {:?}", message_id, e);
+            }
+        }
+    }
+
+    pub async fn on_failure(&self, old_message_id: i64, code: i64, message: &str) {
+        let pending = self.messages.lock().unwrap().remove(&old_message_id);
+        if let Some(pending) = pending {
+            self.fail(pending, code, message).await;
+        }
+    }
+
+    // tdlib rejected the send request itself
+    pub async fn on_error(&self, extra: &str, code: i64, message: &str) {
+        let pending = self.requests.lock().unwrap().remove(extra);
+        if let Some(pending) = pending {
+            self.fail(pending, code, message).await;
+        }
+    }
+
+    async fn fail(&self, pending: Pending, code: i64, message: &str) {
+        error!("Warning for message [{}] in chat [{}] failed : {} {}", pending.reply_to, pending.chat_id, code, message);
+
+        if code == TOO_MANY_REQUESTS {
+            if let Some(seconds) = retry_after(message) {
+                self.hold(seconds);
+            }
+        }
+
+        if message.contains(CHAT_WRITE_FORBIDDEN) || message.contains(USER_BANNED_IN_CHANNEL) {
+            self.forbid_chat(pending.chat_id).await;
+        }
+
+        self.save(MessageSent::failed(pending.reply_to, pending.chat_id, code, message)).await;
+    }
+
+    // Stop sending anything for the given amount of seconds
+    fn hold(&self, seconds: u64) {
+        let until = Instant::now() + Duration::from_secs(seconds);
+        let mut flood_wait = self.flood_wait.lock().unwrap();
+        if flood_wait.map_or(true, |current| current < until) {
+            warn!("Flood wait, holding every message for {} seconds", seconds);
+            *flood_wait = Some(until);
+        }
+    }
+
+    pub async fn wait_flood(&self) {
+        let until = *self.flood_wait.lock().unwrap();
+        if let Some(until) = until {
+            let now = Instant::now();
+            if until > now {
+                info!("Flood wait, waiting {} seconds", (until - now).as_secs());
+                tokio::time::sleep(until - now).await;
+            }
+        }
+    }
+
+    pub async fn is_write_forbidden(&self, chat_id: i64) -> bool {
+        match self.storage.get::<model::Chat>(chat_id).await {
+            Ok(Some(chat)) => chat.is_write_forbidden(),
+            Ok(None) => false,
+            Err(e) => {
+                error!("Failed to get chat '{}' from DB : {:?}", chat_id, e);
+                false
+            }
+        }
+    }
+
+    async fn forbid_chat(&self, chat_id: i64) {
+        self.update_chat(ChatUpdate::forbid_write(chat_id)).await;
+    }
+
+    async fn update_chat(&self, update: ChatUpdate) {
+        if let Err(e) = self.storage.save(&update).await {
+            error!("Failed to update chat '{}' in DB : {:?}", update.id(), e);
+        }
+    }
+
+    pub async fn save(&self, message_sent: MessageSent) {
+        if let Err(e) = self.storage.save(&message_sent).await {
+            error!("Failed to save delivery in DB : {:?}", e);
+        }
+    }
+
+}
+
+fn retry_after(message: &str) -> Option<u64> {
+    Regex::new("(?:retry after |FLOOD_WAIT_)(?P<time>\\d+)").ok()
+        .and_then(|regex| regex.captures(message).map(|caps| caps["time"].to_string()))
+        .and_then(|time| time.parse::<u64>().ok())
+}
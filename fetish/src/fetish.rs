@@ -1,50 +1,107 @@
-use log::{info, debug, error};
+use log::{info, debug, warn, error};
 use config::{Config, LogType};
 use telegram_client::api::Api;
 use telegram_client::client::Client;
 use crate::auth;
-use rtdlib::types::{RObject, GetUser, MessageSender};
-use std::sync::{Arc, Mutex, mpsc};
+use rtdlib::types::{RObject, GetUser, MessageSender, Message, Chat, User};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::sync::mpsc::UnboundedSender;
 use telegram_client::api::aevent::EventApi;
 use crate::scam_analyser::{ScamAnalyser};
 use telegram_client::listener::Listener;
 use rtdlib::types::MessageContent::{MessagePhoto, MessageVideo, MessageText};
-use crate::mongo::Mongo;
-use std::sync::mpsc::Sender;
-use model::{Sanction, Record};
+use crate::storage::Storage;
+use model::{ChatUpdate, Merge, MessageSeen, Record, Sanction};
+use model::cipher::KeyError;
 use crate::recorder::Recorder;
+use crate::delivery::Delivery;
+use crate::moderator::Moderator;
+use crate::reporter::Reporter;
+use crate::private_chat::PrivateChat;
+use crate::cleaner::Cleaner;
+use crate::migration;
+use crate::cache::Cache;
+use crate::retention::Retention;
+use crate::rollup::Rollups;
+use crate::redact::{self, Sensitive};
+
+// Updates handled at once, the others wait for one of them to finish
+static CONCURRENT_UPDATES: usize = 16;
 
 #[derive(Clone)]
 pub struct Fetish {
     config: Config,
     api: EventApi,
-    analyser: ScamAnalyser<Mongo>,
-    tx: Arc<Mutex<Sender<Sanction>>>,
-    mongo: Mongo
+    runtime: Handle,
+    permits: Arc<Semaphore>,
+    analyser: ScamAnalyser<Storage>,
+    tx: UnboundedSender<Sanction>,
+    storage: Storage,
+    delivery: Delivery,
+    moderator: Moderator,
+    private_chat: PrivateChat,
+    cleaner: Cleaner
 }
 
 impl Fetish {
 
-    pub fn new(conf_path: &str) -> Self {
+    pub async fn new(conf_path: &str) -> Self {
         let config = get_config(conf_path);
+        match init_cipher() {
+            Ok(true) => {},
+            Ok(false) => warn!("{} isn't set, phone numbers and message contents are stored in clear", model::cipher::KEY_VAR),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
         let api = Api::event();
-        let mongo = Mongo::new(config.clone());
-
-        let (tx, rx) = mpsc::channel();
-
-        let sender_config = config.clone();
-        let sender_api = api.clone();
-        let sender_mongo = mongo.clone();
-        std::thread::spawn(move || {
-            crate::message_sender::Sender::new(sender_config, sender_api, sender_mongo, rx).run();
-        });
+        let storage = match Storage::new(config.clone()).await {
+            Ok(storage) => storage,
+            Err(e) => {
+                error!("Can't open the storage : {:?}", e);
+                std::process::exit(1);
+            }
+        };
+        if let Some(mongo) = storage.as_mongo() {
+            if let Err(e) = mongo.migrate_composite_keys().await {
+                error!("Failed to migrate sanctions to composite keys : {:?}", e);
+            }
+            mongo.ensure_indexes().await;
+        }
+        migration::run(&storage).await;
+        let cache = Cache::new();
+        cache.load(&storage).await;
+        tokio::spawn(cache.clone().follow(storage.clone(), Duration::from_secs(config.storage.refresh)));
+        let delivery = Delivery::new(config.clone(), storage.clone());
+        let moderator = Moderator::new(config.clone(), api.clone());
+        let reporter = Reporter::new(config.clone(), api.clone(), storage.clone());
+        let private_chat = PrivateChat::new(config.clone(), api.clone(), storage.clone(), reporter.clone());
+        let cleaner = Cleaner::new(config.clone(), api.clone(), storage.clone());
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(crate::message_sender::Sender::new(config.clone(), api.clone(), storage.clone(), delivery.clone(), moderator.clone(), reporter, rx).run());
+        tokio::spawn(cleaner.clone().run());
+        tokio::spawn(Retention::new(config.clone(), storage.clone()).run());
+        tokio::spawn(Rollups::new(config.clone(), storage.clone()).run());
 
         Fetish {
             config: config.clone(),
             api: api.clone(),
-            analyser: ScamAnalyser::new(config.clone(), mongo.clone()),
-            tx: Arc::new(Mutex::new(tx)),
-            mongo
+            runtime: Handle::current(),
+            permits: Arc::new(Semaphore::new(CONCURRENT_UPDATES)),
+            analyser: ScamAnalyser::new(config.clone(), storage.clone(), cache),
+            tx,
+            storage,
+            delivery,
+            moderator,
+            private_chat,
+            cleaner
         }
     }
 
@@ -57,87 +114,161 @@ impl Fetish {
 
         self.listen_new_messages(listener);
         self.listen_new_chats(listener);
+        self.listen_chat_updates(listener);
         self.listen_new_users(listener);
+        self.listen_deliveries(listener);
+        self.listen_rights(listener);
+        self.listen_deleted_messages(listener);
 
         client.daemon("fetish-rs").expect("Failed to start daemon");
     }
 
+    // Handles an update on the runtime, so tdlib's update loop never waits on the database
+    fn spawn(&self, handler: impl Future<Output = ()> + Send + 'static) {
+        let permits = self.permits.clone();
+        self.runtime.spawn(async move {
+            let _permit = permits.acquire().await.unwrap();
+            handler.await;
+        });
+    }
+
     fn listen_new_messages(&self, listener: &mut Listener) {
         let fetish = self.clone();
 
-        listener.on_update_new_message(move |(api, update)| {
+        listener.on_update_new_message(move |(_api, update)| {
+            let handler = fetish.clone();
             let message = update.message().clone();
+            fetish.spawn(async move { handler.on_new_message(message).await });
+            Ok(())
+        });
+    }
 
-            // Save user in DB if doesn't exist
-            if let MessageSender::User(message_sender_user) = message.sender() {
-                if fetish.mongo.get_doc(model::USERS_COLLECTION, message_sender_user.user_id()).unwrap().is_none() {
-                    api.get_user(GetUser::builder().user_id(message_sender_user.user_id()).build()).unwrap();
+    async fn on_new_message(&self, message: Message) {
+        // Save user in DB if doesn't exist or can't be read
+        if let MessageSender::User(message_sender_user) = message.sender() {
+            if !matches!(self.storage.get::<model::User>(message_sender_user.user_id()).await, Ok(Some(_))) {
+                if let Err(e) = self.api.get_user(GetUser::builder().user_id(message_sender_user.user_id()).build()) {
+                    error!("Failed to get user '{}' : {:?}", message_sender_user.user_id(), e);
                 }
             }
+        }
 
-            info!("Getting new message");
-            debug!("Message, from: '{:?}', data: {}", message.sender(), message.to_json().expect("Can't serialize json"));
-
-            ///// SHOW MESSAGE IN CONSOLE
-            if let Some(content) = match message.content() {
-                MessagePhoto(message_photo) => Some(message_photo.caption().text()),
-                MessageVideo(message_video) => Some(message_video.caption().text()),
-                MessageText(message_text) => Some(message_text.text().text()),
-                _ => None
-            } {
-                debug!("MESSAGE'S CONTENT : {}", content);
+        info!("Getting new message");
+        if message.chat_id() < 0 {
+            self.update_chat(ChatUpdate::activity(message.chat_id(), message.date())).await;
+            if let Err(e) = self.storage.save(&MessageSeen::new(message.chat_id(), message.date())).await {
+                error!("Failed to count message [{}] in the rollups : {:?}", message.id(), e);
             }
-            /////
+        }
+        debug!("Message, from: '{:?}', data: {}", message.sender(), Sensitive(&message.to_json().expect("Can't serialize json")));
+
+        ///// SHOW MESSAGE IN CONSOLE
+        if let Some(content) = match message.content() {
+            MessagePhoto(message_photo) => Some(message_photo.caption().text()),
+            MessageVideo(message_video) => Some(message_video.caption().text()),
+            MessageText(message_text) => Some(message_text.text().text()),
+            _ => None
+        } {
+            debug!("MESSAGE'S CONTENT : {}", Sensitive(content));
+        }
+        /////
+
+        // Analyse threat
+        if !self.analyser.is_threat(&message).await {
+            info!("The message is not a threat");
+            return;
+        }
+        info!("The message is a threat");
+
+        // Analyse scam
+        let analyse = self.analyser.analyse(&message).await;
 
-            // Analyse threat
-            if !fetish.analyser.is_threat(&message) {
-                info!("The message is not a threat");
-                return Ok(());
+        if analyse.is_empty() {
+            info!("The message is not a scam");
+            // Save message in DB
+            if let Err(e) = self.storage.save(&model::Message::from_td(&message, false)).await {
+                error!("Failed to save message [{}] : {:?}", message.id(), e);
             }
-            info!("The message is a threat");
-
-            // Analyse scam
-            let analyse = fetish.analyser.analyse(&message);
-
-            if analyse.is_empty() {
-                info!("The message is not a scam");
-                // Save message in DB
-                fetish.mongo.save_doc(model::Message::from_td(&message, false)).unwrap();
-                return Ok(());
-            } else {
-                info!("SCAM DETECTED !!!");
-                info!("SCAM DETECTED !!!");
-                info!("SCAM DETECTED !!!");
-                // Save message in DB
-                fetish.mongo.save_doc(model::Message::from_td(&message, true)).unwrap();
+            return;
+        } else {
+            info!("SCAM DETECTED !!!");
+            info!("SCAM DETECTED !!!");
+            info!("SCAM DETECTED !!!");
+            // Save message in DB
+            if let Err(e) = self.storage.save(&model::Message::from_td(&message, true)).await {
+                error!("Failed to save message [{}] : {:?}", message.id(), e);
             }
-
             if message.chat_id() < 0 {
-                if let Err(e) = fetish.tx.lock().unwrap().send(Sanction::new(message.clone(), analyse)) {
-                    error!("FAILED TO SEND SANCTION : {}", e.to_string());
-                }
-            } else {
-                info!("This is a private chat, not sending the message");
+                self.update_chat(ChatUpdate::scam(message.chat_id())).await;
             }
+        }
 
-            Ok(())
-        });
+        if message.chat_id() < 0 {
+            if let Err(e) = self.tx.send(Sanction::new(message.clone(), analyse)) {
+                error!("FAILED TO SEND SANCTION : {}", e.to_string());
+            }
+        } else {
+            info!("This is a private chat, applying the private chat policy");
+            self.private_chat.handle(&message).await;
+        }
     }
 
     fn listen_new_chats(&self, listener: &mut Listener) {
         let fetish = self.clone();
 
         listener.on_update_new_chat(move |(_api, update)| {
-            let chat = update.chat();
-            info!("Chat {} info", chat.title());
-
-            // Save chat in DB
-            match fetish.mongo.get_doc(model::CHATS_COLLECTION, chat.id()) {
-                Ok(Some(_)) => info!("Chat '{}' already exists in DB", chat.id()),
-                Ok(None) => if let Err(e) = fetish.mongo.save_doc(model::Chat::from_td(&chat)) { error!("Failed to save chat '{}' in DB : {:?}", chat.id(), e); },
-                Err(e) => error!("Failed to get chat '{}' from DB : {:?}", chat.id(), e)
-            }
+            let handler = fetish.clone();
+            let chat = update.chat().clone();
+            fetish.spawn(async move { handler.on_new_chat(chat).await });
+            Ok(())
+        });
+    }
+
+    async fn on_new_chat(&self, chat: Chat) {
+        info!("Chat {} info", chat.title());
+
+        // Save chat in DB, or refresh it
+        self.update_chat(ChatUpdate::from_td(&chat)).await;
+    }
+
+    async fn update_chat(&self, update: ChatUpdate) {
+        if let Err(e) = self.storage.save(&update).await {
+            error!("Failed to update chat '{}' in DB : {:?}", update.id(), e);
+        }
+    }
+
+    fn listen_chat_updates(&self, listener: &mut Listener) {
+        let fetish = self.clone();
+        listener.on_update_chat_title(move |(_api, update)| {
+            let handler = fetish.clone();
+            let update = ChatUpdate::title(update.chat_id(), update.title());
+            fetish.spawn(async move { handler.update_chat(update).await });
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_update_chat_permissions(move |(_api, update)| {
+            let handler = fetish.clone();
+            let update = ChatUpdate::permissions(update.chat_id(), update.permissions());
+            fetish.spawn(async move { handler.update_chat(update).await });
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_update_supergroup_full_info(move |(_api, update)| {
+            let handler = fetish.clone();
+            let info = update.supergroup_full_info();
+            let update = ChatUpdate::full_info(crate::moderator::supergroup_chat_id(update.supergroup_id()), info.description(), info.member_count());
+            fetish.spawn(async move { handler.update_chat(update).await });
+            Ok(())
+        });
 
+        let fetish = self.clone();
+        listener.on_update_basic_group_full_info(move |(_api, update)| {
+            let handler = fetish.clone();
+            let info = update.basic_group_full_info();
+            let update = ChatUpdate::full_info(crate::moderator::basic_group_chat_id(update.basic_group_id()), info.description(), info.members().len() as i64);
+            fetish.spawn(async move { handler.update_chat(update).await });
             Ok(())
         });
     }
@@ -146,51 +277,151 @@ impl Fetish {
         let fetish = self.clone();
 
         listener.on_update_user(move |(_api, update)| {
-            let user = update.user();
-            info!("User '{} {}' info", user.first_name(), user.last_name());
-
-            // Save user in DB
-            match fetish.mongo.get_doc(model::USERS_COLLECTION, user.id()) {
-                Ok(Some(u)) => {
-                    info!("User '{}' already exists in DB, update", user.id());
-                    let mut user_updt = model::User::from_doc(&u);
-                    if !user_updt.scam && !user_updt.is_bypass() {
-                        info!("Checking if {} {} is a scammer", user.first_name(), user.last_name());
-                        user_updt.scam = fetish.analyser.is_new_user_scam(&user);
-                        if user_updt.scam {
-                            info!("Updated user {} {} is a scammer", user.first_name(), user.last_name());
-                        } else {
-                            info!("Updated user {} {} may not be a scammer", user.first_name(), user.last_name());
-                        }
-                    }
-                    user_updt.merge(&model::User::from_td(user, user_updt.scam));
-                    debug!("New user info {:?}", user_updt);
-                    if let Err(e) = fetish.mongo.save_doc(user_updt) {
-                        error!("Failed to update user '{}' in DB : {:?}", user.id(), e);
-                    }
-                },
-                Ok(None) => {
-                    let scam = fetish.analyser.is_new_user_scam(&user);
-                    if scam {
-                        info!("New user {} {} is a scammer", user.first_name(), user.last_name());
-                    }
-                    if let Err(e) = fetish.mongo.save_doc(model::User::from_td(&user, scam)) {
-                        error!("Failed to save user '{}' in DB : {:?}", user.id(), e);
+            let handler = fetish.clone();
+            let user = update.user().clone();
+            fetish.spawn(async move { handler.on_user(user).await });
+            Ok(())
+        });
+    }
+
+    async fn on_user(&self, user: User) {
+        info!("User '{} {}' info", user.first_name(), user.last_name());
+
+        // Save user in DB
+        match self.storage.get::<model::User>(user.id()).await {
+            Ok(Some(mut user_updt)) => {
+                info!("User '{}' already exists in DB, update", user.id());
+                if !user_updt.scam && !user_updt.is_bypass() {
+                    info!("Checking if {} {} is a scammer", user.first_name(), user.last_name());
+                    user_updt.scam = self.analyser.is_new_user_scam(&user).await;
+                    if user_updt.scam {
+                        info!("Updated user {} {} is a scammer", user.first_name(), user.last_name());
+                    } else {
+                        info!("Updated user {} {} may not be a scammer", user.first_name(), user.last_name());
                     }
-                },
-                Err(e) => error!("Failed to get user '{}' from DB : {:?}", user.id(), e)
+                }
+                user_updt.merge(&model::User::from_td(&user, user_updt.scam));
+                if !user_updt.scam && !user_updt.is_bypass() && self.analyser.is_frequent_renamer(&user_updt) {
+                    info!("User {} {} renamed {} times, flagged as a scammer", user.first_name(), user.last_name(), user_updt.names().len());
+                    user_updt.scam = true;
+                }
+                debug!("New user info {:?}", user_updt);
+                if let Err(e) = self.storage.save(&user_updt).await {
+                    error!("Failed to update user '{}' in DB : {:?}", user.id(), e);
+                }
+            },
+            Ok(None) => {
+                let scam = self.analyser.is_new_user_scam(&user).await;
+                if scam {
+                    info!("New user {} {} is a scammer", user.first_name(), user.last_name());
+                }
+                if let Err(e) = self.storage.save(&model::User::from_td(&user, scam)).await {
+                    error!("Failed to save user '{}' in DB : {:?}", user.id(), e);
+                }
+            },
+            Err(e) => error!("Failed to get user '{}' from DB : {:?}", user.id(), e)
+        }
+    }
+
+    fn listen_deliveries(&self, listener: &mut Listener) {
+        let delivery = self.delivery.clone();
+        listener.on_message(move |(_api, message)| {
+            if let Some(extra) = message.extra() {
+                delivery.on_message(&extra, message.id());
+            }
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_update_message_send_succeeded(move |(_api, update)| {
+            let delivery = fetish.delivery.clone();
+            let (old_message_id, message_id) = (update.old_message_id(), update.message().id());
+            fetish.spawn(async move { delivery.on_success(old_message_id, message_id).await });
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_update_message_send_failed(move |(_api, update)| {
+            let delivery = fetish.delivery.clone();
+            let (old_message_id, code, message) = (update.old_message_id(), update.error_code(), update.error_message().to_string());
+            fetish.spawn(async move { delivery.on_failure(old_message_id, code, &message).await });
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_error(move |(_api, err)| {
+            error!("tdlib error {} : {}", err.code(), err.message());
+            if let Some(extra) = err.extra() {
+                let delivery = fetish.delivery.clone();
+                let (code, message) = (err.code(), err.message().to_string());
+                fetish.spawn(async move { delivery.on_error(&extra, code, &message).await });
             }
+            Ok(())
+        });
+    }
+
+    fn listen_rights(&self, listener: &mut Listener) {
+        let fetish = self.clone();
+        listener.on_update_supergroup(move |(_api, update)| {
+            let supergroup = update.supergroup();
+            let chat_id = crate::moderator::supergroup_chat_id(supergroup.id());
+            fetish.moderator.set_rights(chat_id, supergroup.status());
+
+            let handler = fetish.clone();
+            let update = ChatUpdate::membership(chat_id, supergroup.status(), supergroup.member_count());
+            fetish.spawn(async move { handler.update_chat(update).await });
+            Ok(())
+        });
+
+        let fetish = self.clone();
+        listener.on_update_basic_group(move |(_api, update)| {
+            let basic_group = update.basic_group();
+            let chat_id = crate::moderator::basic_group_chat_id(basic_group.id());
+            fetish.moderator.set_rights(chat_id, basic_group.status());
+
+            let handler = fetish.clone();
+            let update = ChatUpdate::membership(chat_id, basic_group.status(), basic_group.member_count());
+            fetish.spawn(async move { handler.update_chat(update).await });
+            Ok(())
+        });
+    }
 
+    fn listen_deleted_messages(&self, listener: &mut Listener) {
+        let fetish = self.clone();
+        listener.on_update_delete_messages(move |(_api, update)| {
+            if update.is_permanent() && !update.from_cache() {
+                let cleaner = fetish.cleaner.clone();
+                let (chat_id, message_ids) = (update.chat_id(), update.message_ids().clone());
+                fetish.spawn(async move { cleaner.on_messages_deleted(chat_id, &message_ids).await });
+            }
             Ok(())
         });
     }
 
 }
 
+/**
+*   Sets the key encrypting the stored phone numbers and message contents, from the environment.
+*   Returns whether a key is set, without one they're stored in clear.
+*/
+pub fn init_cipher() -> Result<bool, KeyError> {
+    match std::env::var(model::cipher::KEY_VAR) {
+        Ok(key) => model::cipher::init(&key).map(|_| true),
+        Err(_) => Ok(false)
+    }
+}
+
 fn get_config(conf_path: &str) -> Config {
-    let config = Config::from(conf_path);
+    let config = match Config::from(conf_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid config file '{}' : {}", conf_path, e);
+            std::process::exit(1);
+        }
+    };
 
     config.log().map(|v| {
+        redact::allow_sensitive(v.sensitive);
         Client::set_log_verbosity_level(v.level.clone() as i32).unwrap();
 
         if v.type_ == LogType::File {
@@ -1,6 +1,7 @@
 use simple_logger::SimpleLogger;
 
 use crate::fetish::Fetish;
+use crate::redact::Redactor;
 
 mod thelp;
 mod tgfn;
@@ -9,12 +10,40 @@ mod message_sender;
 mod fetish;
 mod mongo;
 mod recorder;
+mod storage;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod scam_analyser;
+mod delivery;
+mod moderator;
+mod reporter;
+mod private_chat;
+mod cleaner;
+mod migration;
+mod cache;
+mod retention;
+mod rollup;
+mod redact;
+mod transfer;
+mod sync;
+mod audit;
+mod command;
+#[cfg(test)]
+mod memory;
 
-fn main() {
-    SimpleLogger::new().init().unwrap();
+#[tokio::main]
+async fn main() {
+    log::set_boxed_logger(Box::new(Redactor::new(SimpleLogger::new()))).unwrap();
     log::set_max_level(log::LevelFilter::Debug);
     let args: Vec<String> = std::env::args().collect();
 
-    Fetish::new(args.get(1).unwrap()).run();
+    let conf_path = args.get(1).unwrap();
+
+    match args.get(2) {
+        Some(_) => if let Err(e) = command::run(conf_path, &args[2..]).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+        None => Fetish::new(conf_path).await.run()
+    }
 }
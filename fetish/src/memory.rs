@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use mongodb::bson::{Bson, Document};
+use model::{Key, Record};
+use crate::recorder::{apply_update, outdated, upgrade_doc, Filter, Recorder, RecorderError, Value};
+
+/**
+*   Recorder keeping the records in memory
+*/
+#[derive(Clone, Default)]
+pub struct Memory {
+    collections: Arc<Mutex<HashMap<String, Vec<Document>>>>
+}
+
+impl Memory {
+
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    /**
+    *   Inserts a raw document, as written by an older version
+    */
+    pub fn insert(&self, collection: &str, doc: Document) {
+        self.collections.lock().unwrap().entry(collection.to_string()).or_default().push(doc);
+    }
+
+}
+
+#[async_trait]
+impl Recorder for Memory {
+
+    type Error = RecorderError<Infallible>;
+
+    async fn get<T: Record>(&self, key: impl Into<Key> + Send) -> Result<Option<T>, Self::Error> {
+        Ok(self.find::<T>(Filter::from(key.into())).await?.into_iter().next())
+    }
+
+    async fn find<T: Record>(&self, filter: Filter) -> Result<Vec<T>, Self::Error> {
+        let collections = self.collections.lock().unwrap();
+        collections.get(T::collection())
+            .map(|docs| docs.iter().filter(|doc| matches(doc, &filter)).map(T::from_doc).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))
+            .map_err(RecorderError::Decode)
+    }
+
+    async fn count<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let collections = self.collections.lock().unwrap();
+        Ok(collections.get(T::collection())
+            .map_or(0, |docs| docs.iter().filter(|doc| matches(doc, &filter)).count() as u64))
+    }
+
+    async fn save(&self, record: &impl Record) -> Result<(), Self::Error> {
+        let filter = Filter::from(record.key());
+        let mut collections = self.collections.lock().unwrap();
+        let docs = collections.entry(record.collection_name().to_string()).or_default();
+
+        match docs.iter_mut().find(|doc| matches(doc, &filter)) {
+            Some(doc) => apply_update(doc, &record.to_doc_update()),
+            None => docs.push(record.to_doc())
+        }
+        Ok(())
+    }
+
+    async fn save_all<T: Record>(&self, records: Vec<T>) -> Result<(), Self::Error> {
+        for record in records {
+            self.save(&record).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete<T: Record>(&self, key: impl Into<Key> + Send) -> Result<bool, Self::Error> {
+        let filter = Filter::from(key.into());
+        let mut collections = self.collections.lock().unwrap();
+        let docs = match collections.get_mut(T::collection()) {
+            Some(docs) => docs,
+            None => return Ok(false)
+        };
+
+        match docs.iter().position(|doc| matches(doc, &filter)) {
+            Some(index) => {
+                docs.remove(index);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    async fn delete_all<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let mut collections = self.collections.lock().unwrap();
+        Ok(collections.get_mut(T::collection()).map_or(0, |docs| {
+            let before = docs.len();
+            docs.retain(|doc| !matches(doc, &filter));
+            (before - docs.len()) as u64
+        }))
+    }
+
+    async fn unset<T: Record>(&self, filter: Filter, field: &str) -> Result<u64, Self::Error> {
+        let mut collections = self.collections.lock().unwrap();
+        Ok(collections.get_mut(T::collection()).map_or(0, |docs| {
+            docs.iter_mut()
+                .filter(|doc| matches(doc, &filter))
+                .filter_map(|doc| doc.remove(field))
+                .count() as u64
+        }))
+    }
+
+    async fn upgrade<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let filter = filter.and(outdated::<T>());
+        let mut collections = self.collections.lock().unwrap();
+        Ok(collections.get_mut(T::collection()).map_or(0, |docs| {
+            docs.iter_mut()
+                .filter(|doc| matches(doc, &filter))
+                .map(upgrade_doc::<T>)
+                .count() as u64
+        }))
+    }
+
+}
+
+fn compare(bson: &Bson, value: &Value) -> Option<Ordering> {
+    match (bson, value) {
+        (Bson::Int64(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Bson::Int32(a), Value::Int(b)) => Some((*a as i64).cmp(b)),
+        (Bson::Boolean(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Bson::String(a), Value::Str(b)) => Some(a.as_str().cmp(b.as_str())),
+        _ => None
+    }
+}
+
+pub fn matches(doc: &Document, filter: &Filter) -> bool {
+    let field_cmp = |field: &String, value: &Value| doc.get(field).and_then(|bson| compare(bson, value));
+
+    match filter {
+        Filter::All => true,
+        Filter::Eq(field, value) => field_cmp(field, value) == Some(Ordering::Equal),
+        Filter::Gt(field, value) => field_cmp(field, value) == Some(Ordering::Greater),
+        Filter::Gte(field, value) => matches!(field_cmp(field, value), Some(Ordering::Greater) | Some(Ordering::Equal)),
+        Filter::Lt(field, value) => field_cmp(field, value) == Some(Ordering::Less),
+        Filter::Lte(field, value) => matches!(field_cmp(field, value), Some(Ordering::Less) | Some(Ordering::Equal)),
+        Filter::In(field, values) => values.iter().any(|value| field_cmp(field, value) == Some(Ordering::Equal)),
+        Filter::Exists(field, exists) => doc.contains_key(field) == *exists,
+        Filter::And(filters) => filters.iter().all(|filter| matches(doc, filter)),
+        Filter::Or(filters) => filters.iter().any(|filter| matches(doc, filter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder, RecorderError};
+    use crate::recorder::tests::check_recorder;
+    use model::{Chat, ChatUpdate, Record, User};
+    use mongodb::bson::doc;
+
+    #[tokio::test]
+    async fn chat_updates_set_and_increment() {
+        let memory = Memory::new();
+        memory.save(&ChatUpdate::scam(-1)).await.unwrap();
+        memory.save(&ChatUpdate::title(-1, "Rencontres")).await.unwrap();
+        memory.save(&ChatUpdate::scam(-1)).await.unwrap();
+        memory.save(&ChatUpdate::warning(-1)).await.unwrap();
+
+        let chat = memory.get::<Chat>(-1).await.unwrap().unwrap();
+        assert_eq!(chat.title(), "Rencontres");
+        assert_eq!((chat.scam_count(), chat.warning_count()), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn behaves_like_a_recorder() {
+        check_recorder(Memory::new()).await;
+    }
+
+    #[tokio::test]
+    async fn upgrade_fills_documents_written_by_older_versions() {
+        let memory = Memory::new();
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 1_i64, "first_name": "Alice" });
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 2_i64 });
+
+        assert_eq!(memory.upgrade::<User>(Filter::eq("id", 1)).await.unwrap(), 1);
+        assert_eq!(memory.upgrade::<User>(Filter::eq("id", 1)).await.unwrap(), 0);
+        let user = memory.get::<User>(1).await.unwrap().unwrap();
+        assert_eq!(user.to_doc().get_i64(model::SCHEMA_VERSION).unwrap(), User::SCHEMA_VERSION);
+
+        match memory.get::<User>(2).await {
+            Err(RecorderError::Decode(error)) => assert!(error.to_string().contains("first_name")),
+            _ => panic!("a user without first name must not decode")
+        }
+    }
+
+}
@@ -1,34 +1,43 @@
-use log::{info, debug};
-use rtdlib::types::{SendMessage, InputMessageContent, InputMessageText, FormattedText};
+use log::{info, debug, error};
+use rtdlib::types::{RObject, SendMessage, InputMessageContent, InputMessageText, FormattedText};
 use rand::Rng;
-use config::Config;
+use config::{Action, Config};
 use telegram_client::api::aevent::EventApi;
-use std::sync::mpsc::Receiver;
-use crate::mongo::Mongo;
-use model::{Sanction, ScamType};
+use tokio::sync::mpsc::UnboundedReceiver;
+use crate::storage::Storage;
+use model::{Sanction, ScamType, MessageSent};
 use crate::recorder::Recorder;
+use crate::delivery::{Delivery, CHAT_WRITE_FORBIDDEN};
+use crate::moderator::Moderator;
+use crate::reporter::Reporter;
 
 pub struct Sender {
     config: Config,
     api: EventApi,
-    mongo: Mongo,
-    rx: Receiver<Sanction>
+    storage: Storage,
+    delivery: Delivery,
+    moderator: Moderator,
+    reporter: Reporter,
+    rx: UnboundedReceiver<Sanction>
 }
 
 impl Sender {
 
-    pub fn new(config: Config, api: EventApi, mongo: Mongo, rx: Receiver<Sanction>) -> Self {
+    pub fn new(config: Config, api: EventApi, storage: Storage, delivery: Delivery, moderator: Moderator, reporter: Reporter, rx: UnboundedReceiver<Sanction>) -> Self {
         Sender {
             config: config.clone(),
             api,
-            mongo,
+            storage,
+            delivery,
+            moderator,
+            reporter,
             rx
         }
     }
 
-    pub fn run(self) {
-        for sanction in &self.rx {
-            self.send(&sanction);
+    pub async fn run(mut self) {
+        while let Some(mut sanction) = self.rx.recv().await {
+            self.send(&mut sanction).await;
         }
     }
 
@@ -37,7 +46,7 @@ impl Sender {
 
         for scam_type in scam_types {
             match scam_type {
-                ScamType::Keyword => {
+                ScamType::Keyword { .. } => {
                     if txt.is_empty() {
                         txt.push_str(std::fs::read_to_string(self.config.message_path.as_str()).unwrap().as_str());
                     }
@@ -57,9 +66,41 @@ impl Sender {
         txt
     }
 
-    fn send(&self, sanction: &Sanction) {
+    async fn send(&self, sanction: &mut Sanction) {
         info!("Sending sanction for message id [{}]", sanction.message().id());
 
+        let actions = self.moderator.plan(sanction.message().chat_id(), sanction.scam_types());
+
+        // Report before the message gets deleted
+        self.reporter.report_sanction(sanction).await;
+
+        // Only the moderation actions taken are recorded, the stats rely on them.
+        // Whether the reply went out is only known from its delivery.
+        let mut taken = Vec::new();
+        // Moderation actions don't need to look human
+        for &action in actions.iter().filter(|&&action| action != Action::Reply) {
+            if !self.config.sender.send {
+                info!("DEV : Moderation action '{}' not taken", action.name());
+                continue;
+            }
+            match self.moderator.apply(sanction, action) {
+                Ok(()) => {
+                    info!("Moderation action '{}' taken on message [{}]", action.name(), sanction.message().id());
+                    taken.push(action);
+                },
+                Err(e) => error!("Failed to {} message [{}] : {:?}", action.name(), sanction.message().id(), e)
+            }
+        }
+        let deleted = taken.contains(&Action::Delete) || taken.contains(&Action::DeleteAll);
+        sanction.set_actions(taken.iter().map(|action| action.name().to_string()).collect());
+
+        if !actions.contains(&Action::Reply) {
+            if let Err(e) = self.storage.save(&*sanction).await {
+                error!("Failed to save sanction of message [{}] : {:?}", sanction.message().id(), e);
+            }
+            return;
+        }
+
         // Load artillery
         let txt = self.load_text(sanction.scam_types());
 
@@ -67,26 +108,47 @@ impl Sender {
         let (min, max) = (self.config.sender.min_wait, self.config.sender.max_wait);
         let waiting_time = rand::thread_rng().gen::<f64>() * (max - min) + min;
         info!("Waiting {} seconds", waiting_time);
-        std::thread::sleep(std::time::Duration::from_secs(waiting_time as u64));
+        tokio::time::sleep(std::time::Duration::from_secs(waiting_time as u64)).await;
+
+        // The delivery outcome is stored on the sanction's document
+        if let Err(e) = self.storage.save(&*sanction).await {
+            error!("Failed to save sanction of message [{}] : {:?}", sanction.message().id(), e);
+        }
 
         // Fire
         if self.config.sender.send {
-            self.api.send_message(SendMessage::builder()
-                .chat_id(sanction.message().chat_id())
+            let chat_id = sanction.message().chat_id();
+            if self.delivery.is_write_forbidden(chat_id).await {
+                info!("Can't write in chat [{}], repression not sent", chat_id);
+                self.delivery.save(MessageSent::failed(sanction.message().id(), chat_id, 0, CHAT_WRITE_FORBIDDEN)).await;
+                return;
+            }
+
+            self.delivery.wait_flood().await;
+
+            let request = SendMessage::builder()
+                .chat_id(chat_id)
                 .input_message_content(InputMessageContent::input_message_text(InputMessageText::builder()
                     .text(FormattedText::builder().text(&txt))
                     .clear_draft(true)
                     .disable_web_page_preview(true)
                     .build()))
-                .reply_to_message_id(sanction.message().id())
-                .build()).unwrap();
-            info!("PROD : Repression sent");
+                // Can't reply to a message we just deleted
+                .reply_to_message_id(if deleted { 0 } else { sanction.message().id() })
+                .build();
+            let extra = request.extra().unwrap_or_default();
+            self.delivery.track(extra.clone(), chat_id, sanction.message().id(), sanction.message().date());
+
+            if let Err(e) = self.api.send_message(request) {
+                error!("Failed to send repression : {:?}", e);
+                self.delivery.on_error(&extra, 0, &format!("{:?}", e)).await;
+            } else {
+                info!("PROD : Repression sent");
+            }
         } else {
             info!("DEV : Repression not sent");
             debug!("The message would have been :\n{}", txt);
         }
-
-        self.mongo.save_doc(sanction).unwrap()
     }
 
 }
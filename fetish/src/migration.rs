@@ -0,0 +1,92 @@
+use log::{info, error};
+use model::{cipher, Chat, ForbiddenNames, Key, Keywords, Message, Record, Report, Sanction, User, Warning};
+use crate::recorder::{Filter, Recorder};
+
+/**
+*   Upgrades the documents written by older versions to the current schema versions,
+*   then encrypts the fields still stored in clear
+*/
+pub async fn run<R: Recorder>(recorder: &R) {
+    upgrade::<User, R>(recorder, Filter::All).await;
+    upgrade::<Message, R>(recorder, Filter::All).await;
+    upgrade::<Chat, R>(recorder, Filter::All).await;
+    upgrade::<Report, R>(recorder, Filter::All).await;
+    upgrade::<Warning, R>(recorder, Filter::All).await;
+    upgrade::<Sanction, R>(recorder, Filter::All).await;
+    // Both live in the config collection
+    upgrade::<Keywords, R>(recorder, Filter::from(Key::Id(model::KEYWORDS_ID))).await;
+    upgrade::<ForbiddenNames, R>(recorder, Filter::from(Key::Id(model::FORBIDDEN_NAMES_ID))).await;
+    seal::<User, R>(recorder).await;
+    seal::<Message, R>(recorder).await;
+}
+
+async fn upgrade<T: Record, R: Recorder>(recorder: &R, filter: Filter) {
+    match recorder.upgrade::<T>(filter).await {
+        Ok(0) => {},
+        Ok(upgraded) => info!("Upgraded {} documents of '{}' to version {}", upgraded, T::collection(), T::SCHEMA_VERSION),
+        Err(e) => error!("Failed to upgrade '{}' : {:?}", T::collection(), e)
+    }
+}
+
+// Documents written before the key was set are already stamped with the current version, whatever their fields
+async fn seal<T: Record, R: Recorder>(recorder: &R) {
+    if !cipher::is_set() {
+        return;
+    }
+    let filter = T::ENCRYPTED.iter().fold(Filter::Or(Vec::new()), |filter, &field| filter.or(in_clear(field)));
+    let records = match recorder.find::<T>(filter).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to find the documents of '{}' to encrypt : {:?}", T::collection(), e);
+            return;
+        }
+    };
+    // Saving encrypts the fields again
+    let count = records.len();
+    match recorder.save_all(records).await {
+        Ok(()) if count == 0 => {},
+        Ok(()) => info!("Encrypted {} documents of '{}'", count, T::collection()),
+        Err(e) => error!("Failed to encrypt '{}' : {:?}", T::collection(), e)
+    }
+}
+
+// Non empty strings sorting outside of the "enc:" prefix, the character after ':' being ';'
+fn in_clear(field: &str) -> Filter {
+    Filter::gt(field, "").and(Filter::lt(field, "enc:")).or(Filter::gte(field, "enc;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use model::{Keywords, Record, User};
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+
+    #[tokio::test]
+    async fn run_upgrades_every_collection() {
+        let memory = Memory::new();
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 1_i64, "first_name": "Alice" });
+        memory.insert(model::CONFIG_COLLECTION, doc! { "id": model::KEYWORDS_ID, "fr": ["cc"], "en": [], "de": [] });
+
+        super::run(&memory).await;
+
+        assert!(!memory.get::<User>(1).await.unwrap().unwrap().scam);
+        assert_eq!(memory.count::<Keywords>(Filter::exists(model::SCHEMA_VERSION, true)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn run_encrypts_the_fields_stored_in_clear() {
+        let _ = model::cipher::init("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+        let memory = Memory::new();
+        let version = User::SCHEMA_VERSION;
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 1_i64, "first_name": "Alice", "phone_number": "+33612345678", "schema_version": version });
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 2_i64, "first_name": "Bob", "phone_number": "", "schema_version": version });
+
+        super::run(&memory).await;
+
+        assert_eq!(memory.get::<User>(1).await.unwrap().unwrap().first_name, "Alice");
+        assert_eq!(memory.count::<User>(super::in_clear("phone_number")).await.unwrap(), 0);
+        assert_eq!(memory.count::<User>(Filter::gte("phone_number", "enc:").and(Filter::lt("phone_number", "enc;"))).await.unwrap(), 1);
+    }
+
+}
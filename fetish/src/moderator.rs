@@ -0,0 +1,142 @@
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use config::{Action, Config};
+use rtdlib::errors::RTDError;
+use rtdlib::types::{ChatMemberStatus, ChatMemberStatusBanned, ChatMemberStatusRestricted, ChatPermissions,
+                    DeleteChatMessagesBySender, DeleteMessages, MessageSender, MessageSenderUser, SetChatMemberStatus};
+use telegram_client::api::aevent::EventApi;
+use model::{Sanction, ScamType};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rights {
+    pub can_delete_messages: bool,
+    pub can_restrict_members: bool
+}
+
+impl Rights {
+
+    pub fn from_status(status: &ChatMemberStatus) -> Self {
+        match status {
+            ChatMemberStatus::Creator(_) => Rights { can_delete_messages: true, can_restrict_members: true },
+            ChatMemberStatus::Administrator(admin) => Rights {
+                can_delete_messages: admin.can_delete_messages(),
+                can_restrict_members: admin.can_restrict_members()
+            },
+            _ => Rights::default()
+        }
+    }
+
+    fn allows(&self, action: Action) -> bool {
+        match action {
+            Action::Reply => true,
+            Action::Delete | Action::DeleteAll => self.can_delete_messages,
+            Action::Restrict | Action::Ban => self.can_restrict_members
+        }
+    }
+
+}
+
+/**
+*   Takes the moderation actions configured for a verdict, within the rights our account holds in the chat
+*/
+#[derive(Clone)]
+pub struct Moderator {
+    config: Config,
+    api: EventApi,
+    rights: Arc<Mutex<HashMap<i64, Rights>>>
+}
+
+impl Moderator {
+
+    pub fn new(config: Config, api: EventApi) -> Self {
+        Moderator {
+            config,
+            api,
+            rights: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    pub fn set_rights(&self, chat_id: i64, status: &ChatMemberStatus) {
+        let rights = Rights::from_status(status);
+        info!("Rights in chat [{}] : {:?}", chat_id, rights);
+        self.rights.lock().unwrap().insert(chat_id, rights);
+    }
+
+    pub fn rights(&self, chat_id: i64) -> Rights {
+        self.rights.lock().unwrap().get(&chat_id).cloned().unwrap_or_default()
+    }
+
+    // Actions configured for the verdict, replying instead of the ones we don't have the rights for
+    pub fn plan(&self, chat_id: i64, scam_types: &Vec<ScamType>) -> Vec<Action> {
+        let configured = if scam_types.iter().any(|st| matches!(st, ScamType::Account(_))) {
+            &self.config.moderation.account
+        } else {
+            &self.config.moderation.keyword
+        };
+        let rights = self.rights(chat_id);
+
+        let mut actions: Vec<Action> = configured.iter().cloned().filter(|&action| rights.allows(action)).collect();
+        if actions.len() < configured.len() && !actions.contains(&Action::Reply) {
+            warn!("Missing rights in chat [{}], falling back to reply", chat_id);
+            actions.insert(0, Action::Reply);
+        }
+        actions
+    }
+
+    // Replying is left to the sender, the other actions are requested from tdlib
+    pub fn apply(&self, sanction: &Sanction, action: Action) -> Result<(), RTDError> {
+        let message = sanction.message();
+        let sender_id = message.sender().as_user().map(|user| user.user_id());
+
+        match (action, sender_id) {
+            (Action::Reply, _) => Ok(()),
+            (Action::Delete, _) => self.api.delete_messages(DeleteMessages::builder()
+                .chat_id(message.chat_id())
+                .message_ids(vec![message.id()])
+                .revoke(true)
+                .build()),
+            (Action::Restrict, Some(user_id)) => self.api.set_chat_member_status(SetChatMemberStatus::builder()
+                .chat_id(message.chat_id())
+                .member_id(MessageSender::user(MessageSenderUser::builder().user_id(user_id).build()))
+                .status(ChatMemberStatus::restricted(ChatMemberStatusRestricted::builder()
+                    .is_member(true)
+                    .restricted_until_date(0)
+                    .permissions(ChatPermissions::builder().build())
+                    .build()))
+                .build()),
+            (Action::Ban, Some(user_id)) => self.api.set_chat_member_status(SetChatMemberStatus::builder()
+                .chat_id(message.chat_id())
+                .member_id(MessageSender::user(MessageSenderUser::builder().user_id(user_id).build()))
+                .status(ChatMemberStatus::banned(ChatMemberStatusBanned::builder()
+                    .banned_until_date(0)
+                    .build()))
+                .build()),
+            (Action::DeleteAll, Some(user_id)) => self.api.delete_chat_messages_by_sender(DeleteChatMessagesBySender::builder()
+                .chat_id(message.chat_id())
+                .sender_id(MessageSender::user(MessageSenderUser::builder().user_id(user_id).build()))
+                .build()),
+            (_, None) => Err(RTDError::custom(format!("[{}] has no user sender", message.id())))
+        }
+    }
+
+}
+
+// tdlib chat id of a supergroup
+pub fn supergroup_chat_id(supergroup_id: i64) -> i64 {
+    -1_000_000_000_000 - supergroup_id
+}
+
+// tdlib chat id of a basic group
+pub fn basic_group_chat_id(basic_group_id: i64) -> i64 {
+    -basic_group_id
+}
+
+// Supergroup id of a tdlib chat id, if the chat is a supergroup
+pub fn chat_supergroup_id(chat_id: i64) -> Option<i64> {
+    if chat_id < -1_000_000_000_000 {
+        Some(-1_000_000_000_000 - chat_id)
+    } else {
+        None
+    }
+}
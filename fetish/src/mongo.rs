@@ -1,49 +1,328 @@
-use log::info;
-use mongodb::sync::Client;
+use log::{info, warn, error};
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::change_stream::ChangeStream;
+use mongodb::change_stream::event::ChangeStreamEvent;
 use config::Config;
-use mongodb::bson::{doc, Document};
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::error::Error;
-use model::Record;
-use crate::recorder::Recorder;
+use model::{Key, Record};
+use crate::recorder::{outdated, upgrade_doc, Filter, Recorder, RecorderError, Value};
 
 #[derive(Clone)]
 pub struct Mongo {
     config: Config,
     mdb: Client,
-    db: String
+    db: String,
+    prefix: String
 }
 
 impl Mongo {
 
-    pub fn new(config: Config) -> Self {
-        Mongo {
+    pub async fn new(config: Config) -> Result<Self, Error> {
+        info!("Using database '{}', collection prefix '{}'", config.mongo.database, config.mongo.prefix);
+        Ok(Mongo {
             config: config.clone(),
-            mdb: mongodb::sync::Client::with_uri_str(config.mongo.url.as_str()).unwrap(),
-            db: String::from("fetish")
+            mdb: Client::with_uri_str(config.mongo.url.as_str()).await?,
+            db: config.mongo.database.clone(),
+            prefix: config.mongo.prefix.clone()
+        })
+    }
+
+    // Every collection is reached from here, with the name given by the model, so the prefix is never missed
+    fn collection(&self, name: &str) -> Collection<Document> {
+        self.mdb.database(self.db.as_str()).collection(&format!("{}{}", self.prefix, name))
+    }
+
+    // Changes made to a collection, only available on replica sets
+    pub async fn watch(&self, name: &str) -> Result<ChangeStream<ChangeStreamEvent<Document>>, Error> {
+        self.collection(name).watch(None, None).await
+    }
+
+    /**
+    *   Sanctions used to be keyed by message id only, add the chat id they were missing.
+    *   The chat is found back from the stored message, when a single chat has a message with this id,
+    *   the others get the chat id 0 and `CHAT_UNKNOWN` so they aren't searched again.
+    */
+    pub async fn migrate_composite_keys(&self) -> Result<(), Error> {
+        let stats = self.collection(model::STATS_COLLECTION);
+        let messages = self.collection(model::MESSAGES_COLLECTION);
+
+        let (mut migrated, mut unknown) = (0, 0);
+        let mut sanctions = stats.find(doc! { "chat_id": { "$exists": false } }, None).await?;
+        while let Some(sanction) = sanctions.try_next().await? {
+            let id = match sanction.get_i64("id") {
+                Ok(id) => id,
+                Err(_) => continue
+            };
+
+            let chats: Vec<i64> = messages.find(doc! { "id": id }, None).await?
+                .try_collect::<Vec<Document>>().await?
+                .iter()
+                .filter_map(|message| message.get_i64("chat_id").ok())
+                .collect();
+            let update = match chats.len() {
+                1 => {
+                    migrated += 1;
+                    doc! { "$set": { "chat_id": chats[0] } }
+                },
+                candidates => {
+                    warn!("Can't find the chat of sanction [{}], {} candidates", id, candidates);
+                    unknown += 1;
+                    doc! { "$set": { "chat_id": 0_i64, model::CHAT_UNKNOWN: true } }
+                }
+            };
+            stats.update_one(doc! { "_id": sanction.get("_id").cloned().unwrap() }, update, None).await?;
+        }
+
+        if migrated + unknown > 0 {
+            info!("Composite keys migration : {} sanctions migrated, {} with an unknown chat", migrated, unknown);
+        }
+        Ok(())
+    }
+
+    /**
+    *   Creates the missing indexes.
+    *   A unique index isn't created while its collection holds duplicates, they're reported to be cleaned up by hand.
+    */
+    pub async fn ensure_indexes(&self) {
+        for index in indexes() {
+            let collection = self.collection(index.collection);
+
+            if index.unique {
+                match duplicates(&collection, index.keys).await {
+                    Ok(duplicates) if duplicates.is_empty() => {},
+                    Ok(duplicates) => {
+                        for (key, count) in duplicates.iter() {
+                            warn!("{} documents of '{}' share the key {}", count, index.collection, key);
+                        }
+                        warn!("Unique index on {:?} of '{}' not created, {} keys are duplicated", index.keys, index.collection, duplicates.len());
+                        continue;
+                    },
+                    Err(e) => {
+                        error!("Failed to look for duplicates in '{}' : {:?}", index.collection, e);
+                        continue;
+                    }
+                }
+            }
+
+            let keys: Document = index.keys.iter().map(|key| (key.to_string(), Bson::Int32(1))).collect();
+            let model = IndexModel::builder()
+                .keys(keys)
+                .options(IndexOptions::builder().unique(index.unique).build())
+                .build();
+            match collection.create_index(model, None).await {
+                Ok(_) => info!("Index on {:?} of '{}' ensured", index.keys, index.collection),
+                Err(e) => error!("Failed to create the index on {:?} of '{}' : {:?}", index.keys, index.collection, e)
+            }
         }
     }
 
 }
 
+#[async_trait]
 impl Recorder for Mongo {
 
-    fn get_doc(&self, collection_name: &str, id: i64) -> Result<Option<Document>, Error> {
-        let collection = self.mdb.database(self.db.as_str()).collection(collection_name);
-        collection.find_one(doc! { "id": id }, None)
+    type Error = RecorderError<Error>;
+
+    async fn get<T: Record>(&self, key: impl Into<Key> + Send) -> Result<Option<T>, Self::Error> {
+        self.collection(T::collection()).find_one(key.into().to_doc(), None).await?
+            .map(|doc| T::from_doc(&doc))
+            .transpose()
+            .map_err(RecorderError::Decode)
+    }
+
+    async fn find<T: Record>(&self, filter: Filter) -> Result<Vec<T>, Self::Error> {
+        let docs: Vec<Document> = self.collection(T::collection()).find(filter_to_doc(&filter), None).await?
+            .try_collect().await?;
+        docs.iter()
+            .map(|doc| T::from_doc(doc).map_err(RecorderError::Decode))
+            .collect()
+    }
+
+    async fn count<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        Ok(self.collection(T::collection()).count_documents(filter_to_doc(&filter), None).await?)
     }
 
-    fn save_doc(&self, model: impl Record) -> Result<(), Error> {
-        let collection = self.mdb.database(self.db.as_str()).collection(model.collection_name());
+    async fn save(&self, model: &impl Record) -> Result<(), Self::Error> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        let result = self.collection(model.collection_name()).update_one(model.key().to_doc(), upsert(model), options).await?;
 
-        if self.get_doc(model.collection_name(), model.id())?.is_none() {
-            info!("Saving [{}] in '{}'", model.id(), model.collection_name());
-            collection.insert_one(model.to_doc(), None)?;
+        if result.upserted_id.is_some() {
+            info!("Saved {:?} in '{}'", model.key(), model.collection_name());
         } else {
-            info!("Updating [{}] in '{}'", model.id(), model.collection_name());
-            collection.update_one(doc! { "id": model.id() }, model.to_doc_update(), None)?;
+            info!("Updated {:?} in '{}'", model.key(), model.collection_name());
         }
+        Ok(())
+    }
 
+    async fn save_all<T: Record>(&self, models: Vec<T>) -> Result<(), Self::Error> {
+        for model in models {
+            self.save(&model).await?;
+        }
         Ok(())
     }
 
+    async fn delete<T: Record>(&self, key: impl Into<Key> + Send) -> Result<bool, Self::Error> {
+        let key = key.into();
+        info!("Deleting {:?} from '{}'", key, T::collection());
+        Ok(self.collection(T::collection()).delete_one(key.to_doc(), None).await?.deleted_count > 0)
+    }
+
+    async fn delete_all<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        Ok(self.collection(T::collection()).delete_many(filter_to_doc(&filter), None).await?.deleted_count)
+    }
+
+    async fn unset<T: Record>(&self, filter: Filter, field: &str) -> Result<u64, Self::Error> {
+        let filter = filter.and(Filter::exists(field, true));
+        let update = doc! { "$unset": { field: "" } };
+        Ok(self.collection(T::collection()).update_many(filter_to_doc(&filter), update, None).await?.modified_count)
+    }
+
+    async fn upgrade<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let collection = self.collection(T::collection());
+
+        let mut upgraded = 0;
+        let mut docs = collection.find(filter_to_doc(&filter.and(outdated::<T>())), None).await?;
+        while let Some(mut doc) = docs.try_next().await? {
+            upgrade_doc::<T>(&mut doc);
+            collection.replace_one(doc! { "_id": doc.get("_id").cloned().unwrap() }, doc, None).await?;
+            upgraded += 1;
+        }
+        Ok(upgraded)
+    }
+
+}
+
+struct Index {
+    collection: &'static str,
+    keys: &'static [&'static str],
+    unique: bool
+}
+
+// Record keys are unique, other indexes serve the queries made on every update or by the background jobs
+fn indexes() -> Vec<Index> {
+    vec![
+        Index { collection: model::USERS_COLLECTION, keys: &["id"], unique: true },
+        Index { collection: model::USERS_COLLECTION, keys: &["scam"], unique: false },
+        Index { collection: model::MESSAGES_COLLECTION, keys: &["chat_id", "id"], unique: true },
+        Index { collection: model::MESSAGES_COLLECTION, keys: &["date"], unique: false },
+        Index { collection: model::CHATS_COLLECTION, keys: &["id"], unique: true },
+        Index { collection: model::CONFIG_COLLECTION, keys: &["id"], unique: true },
+        Index { collection: model::STATS_COLLECTION, keys: &["chat_id", "id"], unique: true },
+        Index { collection: model::STATS_COLLECTION, keys: &["date"], unique: false },
+        Index { collection: model::REPORTS_COLLECTION, keys: &["chat_id", "id"], unique: true },
+        Index { collection: model::REPORTS_COLLECTION, keys: &["date"], unique: false },
+        Index { collection: model::WARNINGS_COLLECTION, keys: &["chat_id", "id"], unique: true },
+        Index { collection: model::WARNINGS_COLLECTION, keys: &["expire_date"], unique: false },
+        Index { collection: model::ROLLUPS_COLLECTION, keys: &["id"], unique: true },
+        Index { collection: model::ROLLUPS_COLLECTION, keys: &["day", "dimension"], unique: false },
+        Index { collection: model::AUDIT_COLLECTION, keys: &["id"], unique: true },
+        Index { collection: model::AUDIT_COLLECTION, keys: &["collection", "target"], unique: false }
+    ]
+}
+
+// Keys shared by several documents, with the amount of documents sharing them
+async fn duplicates(collection: &Collection<Document>, keys: &[&str]) -> Result<Vec<(Document, i32)>, Error> {
+    let group: Document = keys.iter().map(|key| (key.to_string(), Bson::String(format!("${}", key)))).collect();
+    let pipeline = vec![
+        doc! { "$group": { "_id": group, "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } }
+    ];
+    let duplicates: Vec<Document> = collection.aggregate(pipeline, None).await?.try_collect().await?;
+    Ok(duplicates.iter()
+        .map(|duplicate| (duplicate.get_document("_id").cloned().unwrap_or_default(), duplicate.get_i32("count").unwrap_or(0)))
+        .collect())
+}
+
+/**
+*   Update saving a record in a single call : the record's update,
+*   and the rest of its document in case it's inserted
+*/
+fn upsert(model: &impl Record) -> Document {
+    let mut update = model.to_doc_update();
+    let set = update.get_document("$set").cloned().unwrap_or_default();
+    let inc = update.get_document("$inc").cloned().unwrap_or_default();
+    // A field can't be both updated and inserted
+    let on_insert: Document = model.to_doc().into_iter()
+        .filter(|(key, _)| !set.contains_key(key) && !inc.contains_key(key))
+        .collect();
+    if !on_insert.is_empty() {
+        update.insert("$setOnInsert", on_insert);
+    }
+    update
+}
+
+fn value_to_bson(value: &Value) -> Bson {
+    match value {
+        Value::Int(i) => Bson::Int64(*i),
+        Value::Bool(b) => Bson::Boolean(*b),
+        Value::Str(s) => Bson::String(s.clone())
+    }
+}
+
+pub fn filter_to_doc(filter: &Filter) -> Document {
+    match filter {
+        Filter::All => doc! {},
+        Filter::Eq(field, value) => doc! { field: value_to_bson(value) },
+        Filter::Gt(field, value) => doc! { field: { "$gt": value_to_bson(value) } },
+        Filter::Gte(field, value) => doc! { field: { "$gte": value_to_bson(value) } },
+        Filter::Lt(field, value) => doc! { field: { "$lt": value_to_bson(value) } },
+        Filter::Lte(field, value) => doc! { field: { "$lte": value_to_bson(value) } },
+        Filter::In(field, values) => doc! { field: { "$in": values.iter().map(value_to_bson).collect::<Vec<Bson>>() } },
+        Filter::Exists(field, exists) => doc! { field: { "$exists": *exists } },
+        Filter::And(filters) if filters.is_empty() => doc! {},
+        Filter::And(filters) => doc! { "$and": filters.iter().map(filter_to_doc).collect::<Vec<Document>>() },
+        Filter::Or(filters) => doc! { "$or": filters.iter().map(filter_to_doc).collect::<Vec<Document>>() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use model::{ChatUpdate, MessageSent, Warning};
+    use crate::mongo::{upsert, Mongo};
+
+    #[tokio::test]
+    async fn collections_are_in_the_configured_database_with_the_prefix() {
+        let mut config = Config::empty();
+        config.mongo.url = String::from("mongodb://localhost:27017");
+        config.mongo.database = String::from("fetish-staging");
+        config.mongo.prefix = String::from("staging_");
+        let mongo = Mongo::new(config).await.unwrap();
+
+        assert_eq!(mongo.collection(model::USERS_COLLECTION).namespace().to_string(), "fetish-staging.staging_users");
+    }
+
+    #[test]
+    fn upsert_sets_the_update_and_inserts_the_rest() {
+        let update = upsert(&MessageSent::sent(10, -1, 11, 0));
+        let set = update.get_document("$set").unwrap();
+        let on_insert = update.get_document("$setOnInsert").unwrap();
+
+        assert!(set.contains_key("delivery"));
+        assert!(!on_insert.contains_key("delivery"));
+        assert_eq!(on_insert.get_i64("chat_id").unwrap(), -1);
+        assert_eq!(on_insert.get_i64("id").unwrap(), 10);
+    }
+
+    #[test]
+    fn upsert_increments_counters_without_inserting_them() {
+        let update = upsert(&ChatUpdate::scam(-1));
+
+        assert_eq!(update.get_document("$inc").unwrap().get_i64("scam_count").unwrap(), 1);
+        assert!(!update.get_document("$setOnInsert").unwrap().contains_key("scam_count"));
+        assert!(update.get_document("$setOnInsert").unwrap().contains_key("title"));
+    }
+
+    #[test]
+    fn upsert_of_a_full_update_inserts_nothing_more() {
+        let update = upsert(&Warning::new(10, -1, 5, 0));
+
+        assert!(update.get_document("$set").unwrap().contains_key("reply_to"));
+        assert!(!update.contains_key("$setOnInsert"));
+    }
+
 }
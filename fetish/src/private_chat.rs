@@ -0,0 +1,118 @@
+use log::{info, debug, error};
+use config::Config;
+use rtdlib::types::{AddChatToList, ChatList, ChatListArchive, FormattedText, InputMessageContent, InputMessageText,
+                    Message, MessageSender, MessageSenderUser, SendMessage, ToggleMessageSenderIsBlocked};
+use telegram_client::api::aevent::EventApi;
+use crate::storage::Storage;
+use crate::recorder::Recorder;
+use crate::reporter::Reporter;
+
+/**
+*   Policy applied to scammers writing to our account directly
+*/
+#[derive(Clone)]
+pub struct PrivateChat {
+    config: Config,
+    api: EventApi,
+    storage: Storage,
+    reporter: Reporter
+}
+
+impl PrivateChat {
+
+    pub fn new(config: Config, api: EventApi, storage: Storage, reporter: Reporter) -> Self {
+        PrivateChat {
+            config,
+            api,
+            storage,
+            reporter
+        }
+    }
+
+    pub async fn handle(&self, message: &Message) {
+        let user_id = match message.sender().as_user() {
+            Some(user) => user.user_id(),
+            None => return
+        };
+        info!("Scammer [{}] wrote in private chat [{}]", user_id, message.chat_id());
+
+        if self.config.private.flag {
+            self.flag(user_id).await;
+        }
+
+        if !self.config.sender.send {
+            info!("DEV : Private chat policy not applied");
+            return;
+        }
+
+        if self.config.private.reply {
+            self.reply(message);
+        }
+
+        self.reporter.report_chat(message.chat_id(), user_id).await;
+
+        if self.config.private.block {
+            if let Err(e) = self.api.toggle_message_sender_is_blocked(ToggleMessageSenderIsBlocked::builder()
+                .sender(MessageSender::user(MessageSenderUser::builder().user_id(user_id).build()))
+                .is_blocked(true)
+                .build()) {
+                error!("Failed to block [{}] : {:?}", user_id, e);
+            }
+        }
+
+        if self.config.private.archive {
+            if let Err(e) = self.api.add_chat_to_list(AddChatToList::builder()
+                .chat_id(message.chat_id())
+                .chat_list(ChatList::archive(ChatListArchive::builder().build()))
+                .build()) {
+                error!("Failed to archive chat [{}] : {:?}", message.chat_id(), e);
+            }
+        }
+    }
+
+    fn reply(&self, message: &Message) {
+        let txt = match std::fs::read_to_string(self.config.scammer_account_path.as_str()) {
+            Ok(txt) => txt,
+            Err(e) => {
+                error!("Failed to read '{}' : {:?}", self.config.scammer_account_path, e);
+                return;
+            }
+        };
+        debug!("Replying in private chat [{}] :\n{}", message.chat_id(), txt);
+
+        if let Err(e) = self.api.send_message(SendMessage::builder()
+            .chat_id(message.chat_id())
+            .input_message_content(InputMessageContent::input_message_text(InputMessageText::builder()
+                .text(FormattedText::builder().text(&txt))
+                .clear_draft(true)
+                .disable_web_page_preview(true)
+                .build()))
+            .build()) {
+            error!("Failed to reply in private chat [{}] : {:?}", message.chat_id(), e);
+        }
+    }
+
+    async fn flag(&self, user_id: i64) {
+        let user = match self.storage.get::<model::User>(user_id).await {
+            Ok(Some(mut user)) => {
+                if user.is_bypass() {
+                    info!("[{}] has the n-word pass, not flagging", user_id);
+                    return;
+                }
+                user.scam = true;
+                user
+            },
+            Ok(None) => model::User::flagged(user_id),
+            Err(e) => {
+                error!("Failed to get user '{}' from DB : {:?}", user_id, e);
+                return;
+            }
+        };
+
+        info!("Flagging [{}] as a scammer", user_id);
+        if let Err(e) = self.storage.save(&user).await {
+            error!("Failed to flag user '{}' in DB : {:?}", user_id, e);
+        }
+    }
+
+}
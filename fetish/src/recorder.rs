@@ -1,10 +1,249 @@
+use std::fmt::Debug;
+use async_trait::async_trait;
 use mongodb::bson::Document;
-use mongodb::error::Error;
-use model::Record;
+use model::{DecodeError, Field, Key, Record};
 
-pub trait Recorder {
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Str(String)
+}
 
-    fn get_doc(&self, collection_name: &str, id: i64) -> Result<Option<Document>, Error>;
-    fn save_doc(&self, doc: impl Record) -> Result<(), Error>;
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
 
-}
\ No newline at end of file
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+/**
+*   Query on the fields of stored records, translated by each backend
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    All,
+    Eq(String, Value),
+    Gt(String, Value),
+    Gte(String, Value),
+    Lt(String, Value),
+    Lte(String, Value),
+    In(String, Vec<Value>),
+    Exists(String, bool),
+    And(Vec<Filter>),
+    Or(Vec<Filter>)
+}
+
+impl Filter {
+
+    pub fn eq(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Eq(field.to_string(), value.into())
+    }
+
+    pub fn gt(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Gt(field.to_string(), value.into())
+    }
+
+    pub fn gte(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Gte(field.to_string(), value.into())
+    }
+
+    pub fn lt(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Lt(field.to_string(), value.into())
+    }
+
+    pub fn lte(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Lte(field.to_string(), value.into())
+    }
+
+    pub fn is_in<V: Into<Value>>(field: &str, values: Vec<V>) -> Self {
+        Filter::In(field.to_string(), values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn exists(field: &str, exists: bool) -> Self {
+        Filter::Exists(field.to_string(), exists)
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match (self, other) {
+            (Filter::All, other) => other,
+            (filter, Filter::All) => filter,
+            (Filter::And(mut filters), Filter::And(others)) => {
+                filters.extend(others);
+                Filter::And(filters)
+            },
+            (Filter::And(mut filters), other) => {
+                filters.push(other);
+                Filter::And(filters)
+            },
+            (filter, other) => Filter::And(vec![filter, other])
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match (self, other) {
+            (Filter::Or(mut filters), other) => {
+                filters.push(other);
+                Filter::Or(filters)
+            },
+            (filter, other) => Filter::Or(vec![filter, other])
+        }
+    }
+
+}
+
+impl From<Key> for Filter {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Id(id) => Filter::eq("id", id),
+            Key::Chat(chat_id, id) => Filter::eq("chat_id", chat_id).and(Filter::eq("id", id))
+        }
+    }
+}
+
+/**
+*   Failure of a recorder, from its backend or from a stored document it can't decode
+*/
+#[derive(Debug)]
+pub enum RecorderError<E> {
+    Backend(E),
+    Decode(DecodeError)
+}
+
+impl<E> From<E> for RecorderError<E> {
+    fn from(error: E) -> Self {
+        RecorderError::Backend(error)
+    }
+}
+
+/**
+*   Storage of the model's records, whatever the backend
+*/
+#[async_trait]
+pub trait Recorder: Send + Sync {
+
+    type Error: Debug + Send;
+
+    async fn get<T: Record>(&self, key: impl Into<Key> + Send) -> Result<Option<T>, Self::Error>;
+    async fn find<T: Record>(&self, filter: Filter) -> Result<Vec<T>, Self::Error>;
+    async fn count<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error>;
+
+    async fn save(&self, record: &impl Record) -> Result<(), Self::Error>;
+    async fn save_all<T: Record>(&self, records: Vec<T>) -> Result<(), Self::Error>;
+
+    // Returns whether a record was deleted
+    async fn delete<T: Record>(&self, key: impl Into<Key> + Send) -> Result<bool, Self::Error>;
+    // Returns the amount of deleted records
+    async fn delete_all<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error>;
+    // Removes a field from the documents matching the filter, returns how many had it
+    async fn unset<T: Record>(&self, filter: Filter, field: &str) -> Result<u64, Self::Error>;
+
+    // Upgrades the documents matching the filter written with an older schema version, returns how many were upgraded
+    async fn upgrade<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error>;
+
+}
+
+// Documents written before versioning, or with an older version of the record
+pub fn outdated<T: Record>() -> Filter {
+    Filter::exists(model::SCHEMA_VERSION, false).or(Filter::lt(model::SCHEMA_VERSION, T::SCHEMA_VERSION))
+}
+
+pub fn upgrade_doc<T: Record>(doc: &mut Document) {
+    let from = doc.get(model::SCHEMA_VERSION).and_then(i64::from_bson).unwrap_or(0);
+    T::upgrade(doc, from);
+    doc.insert(model::SCHEMA_VERSION, T::SCHEMA_VERSION);
+}
+
+/**
+*   Applies a record's `to_doc_update` to its stored document, for backends without update operators.
+*   Only the `$set` and `$inc` operators are used by the records' updates, `$inc` on integers.
+*/
+#[cfg(any(test, feature = "sqlite"))]
+pub fn apply_update(doc: &mut Document, update: &Document) {
+    if let Ok(set) = update.get_document("$set") {
+        for (key, value) in set.iter() {
+            doc.insert(key.clone(), value.clone());
+        }
+    }
+    if let Ok(inc) = update.get_document("$inc") {
+        for (key, value) in inc.iter() {
+            let current = doc.get(key).and_then(i64::from_bson).unwrap_or(0);
+            doc.insert(key.clone(), current + i64::from_bson(value).unwrap_or(0));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use model::{Key, User, Warning};
+    use crate::recorder::{Filter, Recorder};
+
+    /**
+    *   Behaviour shared by every recorder, each backend runs it on an empty store
+    */
+    pub async fn check_recorder<R: Recorder>(recorder: R) {
+        // Composite keys
+        recorder.save(&Warning::new(10, -1, 5, 0)).await.unwrap();
+        recorder.save(&Warning::new(10, -2, 6, 0)).await.unwrap();
+        assert_eq!(recorder.get::<Warning>(Key::Chat(-1, 10)).await.unwrap().unwrap().reply_to(), 5);
+        assert_eq!(recorder.get::<Warning>(Key::Chat(-2, 10)).await.unwrap().unwrap().reply_to(), 6);
+        assert!(recorder.get::<Warning>(Key::Chat(-3, 10)).await.unwrap().is_none());
+
+        // Saving again updates the record
+        recorder.save(&Warning::new(10, -1, 5, 60)).await.unwrap();
+        assert_eq!(recorder.count::<Warning>(Filter::All).await.unwrap(), 2);
+        assert!(recorder.get::<Warning>(Key::Chat(-1, 10)).await.unwrap().unwrap().expire_date() > 0);
+        assert_eq!(recorder.delete_all::<Warning>(Filter::All).await.unwrap(), 2);
+
+        // Filters
+        recorder.save_all(vec![Warning::new(1, -1, 5, 0), Warning::new(2, -1, 6, 60), Warning::new(3, -2, 7, 60)]).await.unwrap();
+        let expiring = Filter::gt("expire_date", 0);
+        assert_eq!(recorder.find::<Warning>(expiring.clone()).await.unwrap().len(), 2);
+        assert_eq!(recorder.count::<Warning>(Filter::eq("chat_id", -1).and(expiring.clone())).await.unwrap(), 1);
+        assert_eq!(recorder.count::<Warning>(Filter::is_in("reply_to", vec![5, 7])).await.unwrap(), 2);
+        assert_eq!(recorder.count::<Warning>(Filter::eq("chat_id", -2).or(Filter::eq("reply_to", 5))).await.unwrap(), 2);
+        assert_eq!(recorder.count::<Warning>(Filter::exists("chat_id", true)).await.unwrap(), 3);
+
+        // Unset
+        assert_eq!(recorder.unset::<Warning>(Filter::eq("id", 1), "reply_to").await.unwrap(), 1);
+        assert_eq!(recorder.unset::<Warning>(Filter::eq("id", 1), "reply_to").await.unwrap(), 0);
+        assert_eq!(recorder.count::<Warning>(Filter::exists("reply_to", true)).await.unwrap(), 2);
+        recorder.save(&Warning::new(1, -1, 5, 0)).await.unwrap();
+
+        // Deletion
+        assert!(recorder.delete::<Warning>(Key::Chat(-1, 1)).await.unwrap());
+        assert!(!recorder.delete::<Warning>(Key::Chat(-1, 1)).await.unwrap());
+        assert_eq!(recorder.delete_all::<Warning>(expiring).await.unwrap(), 2);
+        assert_eq!(recorder.count::<Warning>(Filter::All).await.unwrap(), 0);
+
+        // Booleans and missing fields
+        recorder.save(&User::flagged(1)).await.unwrap();
+        assert_eq!(recorder.count::<User>(Filter::eq("scam", true)).await.unwrap(), 1);
+        assert_eq!(recorder.count::<User>(Filter::eq("scam", false)).await.unwrap(), 0);
+        assert_eq!(recorder.count::<User>(Filter::exists("chat_id", false)).await.unwrap(), 1);
+    }
+
+}
@@ -0,0 +1,103 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::{Log, Metadata, Record};
+use regex::Regex;
+use simple_logger::SimpleLogger;
+
+static REDACTED: &str = "<redacted>";
+
+// Set by the `sensitive` flag of the `[log]` section, redacted until the config is read
+static SENSITIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn allow_sensitive(allow: bool) {
+    SENSITIVE.store(allow, Ordering::Relaxed);
+}
+
+/**
+*   Value only logged when sensitive data is allowed, for message bodies logged on their own
+*/
+pub struct Sensitive<'a>(pub &'a str);
+
+impl Display for Sensitive<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match SENSITIVE.load(Ordering::Relaxed) {
+            true => f.write_str(self.0),
+            false => f.write_str(REDACTED)
+        }
+    }
+}
+
+/**
+*   Logger removing the phone numbers and message bodies from the lines it writes,
+*   whether they're in TDLib's JSON or in the `Debug` output of its types
+*/
+pub struct Redactor {
+    inner: SimpleLogger,
+    fields: Regex,
+    phone_numbers: Regex
+}
+
+impl Redactor {
+
+    pub fn new(inner: SimpleLogger) -> Self {
+        Redactor {
+            inner,
+            fields: Regex::new(r#"("?(?:phone_number|text)"?\s*:\s*)"(?:[^"\\]|\\.)*""#).unwrap(),
+            phone_numbers: Regex::new(r"\+\d{7,15}").unwrap()
+        }
+    }
+
+    pub fn redact(&self, line: &str) -> String {
+        let line = self.fields.replace_all(line, format!("${{1}}\"{}\"", REDACTED).as_str());
+        self.phone_numbers.replace_all(&line, REDACTED).into_owned()
+    }
+
+}
+
+impl Log for Redactor {
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if SENSITIVE.load(Ordering::Relaxed) {
+            return self.inner.log(record);
+        }
+        let line = self.redact(&record.args().to_string());
+        self.inner.log(&Record::builder()
+            .args(format_args!("{}", line))
+            .metadata(record.metadata().clone())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build());
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use simple_logger::SimpleLogger;
+    use crate::redact::Redactor;
+
+    #[test]
+    fn redacts_json_and_debug_fields() {
+        let redactor = Redactor::new(SimpleLogger::new());
+
+        assert_eq!(
+            redactor.redact(r#"{"@type":"formattedText","text":"Send me \"bitcoins\"","entities":[]}"#),
+            r#"{"@type":"formattedText","text":"<redacted>","entities":[]}"#
+        );
+        assert_eq!(
+            redactor.redact(r#"User { id: 42, phone_number: "33612345678", is_scam: false }"#),
+            r#"User { id: 42, phone_number: "<redacted>", is_scam: false }"#
+        );
+        assert_eq!(redactor.redact("Call +33612345678 in chat [-1001234567890]"), "Call <redacted> in chat [-1001234567890]");
+    }
+
+}
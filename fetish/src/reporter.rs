@@ -0,0 +1,110 @@
+use log::{info, error};
+use std::time::{SystemTime, UNIX_EPOCH};
+use config::Config;
+use rtdlib::types::{ChatReportReason, ChatReportReasonSpam, ReportChat, ReportSupergroupSpam};
+use telegram_client::api::aevent::EventApi;
+use model::{Report, Sanction, ScamType};
+use crate::storage::Storage;
+use crate::recorder::{Filter, Recorder};
+
+/**
+*   Reports scams to Telegram, within a daily budget and never twice for the same message
+*/
+#[derive(Clone)]
+pub struct Reporter {
+    config: Config,
+    api: EventApi,
+    storage: Storage
+}
+
+impl Reporter {
+
+    pub fn new(config: Config, api: EventApi, storage: Storage) -> Self {
+        Reporter {
+            config,
+            api,
+            storage
+        }
+    }
+
+    pub async fn report_sanction(&self, sanction: &Sanction) {
+        let is_account = sanction.scam_types().iter().any(|st| matches!(st, ScamType::Account(_)));
+        if !is_account && !self.config.report.keyword {
+            return;
+        }
+
+        let message = sanction.message();
+        let sender = message.sender().as_user().map_or(0, |user| user.user_id());
+        self.report(message.chat_id(), vec![message.id()], sender).await;
+    }
+
+    pub async fn report_chat(&self, chat_id: i64, sender: i64) {
+        self.report(chat_id, vec![], sender).await;
+    }
+
+    async fn report(&self, chat_id: i64, message_ids: Vec<i64>, sender: i64) {
+        if !self.config.report.enable {
+            return;
+        }
+        if !self.config.sender.send {
+            info!("DEV : report not sent");
+            return;
+        }
+
+        let id = message_ids.first().cloned().unwrap_or(chat_id);
+        if self.is_reported(chat_id, id).await {
+            info!("[{}] has already been reported", id);
+            return;
+        }
+        if !self.has_budget().await {
+            info!("Daily report budget of {} exhausted, not reporting [{}]", self.config.report.daily_budget, id);
+            return;
+        }
+
+        let (kind, result) = match crate::moderator::chat_supergroup_id(chat_id) {
+            Some(supergroup_id) if !message_ids.is_empty() => (model::KIND_SUPERGROUP_SPAM, self.api.report_supergroup_spam(ReportSupergroupSpam::builder()
+                .supergroup_id(supergroup_id)
+                .message_ids(message_ids)
+                .build())),
+            _ => (model::KIND_CHAT, self.api.report_chat(ReportChat::builder()
+                .chat_id(chat_id)
+                .message_ids(message_ids)
+                .reason(ChatReportReason::spam(ChatReportReasonSpam::builder().build()))
+                .build()))
+        };
+
+        match result {
+            Ok(()) => {
+                info!("Reported [{}] in chat [{}] as {}", id, chat_id, kind);
+                if let Err(e) = self.storage.save(&Report::new(id, chat_id, sender, kind, "spam")).await {
+                    error!("Failed to save report of [{}] in DB : {:?}", id, e);
+                }
+            },
+            Err(e) => error!("Failed to report [{}] : {:?}", id, e)
+        }
+    }
+
+    async fn is_reported(&self, chat_id: i64, id: i64) -> bool {
+        match self.storage.get::<Report>((chat_id, id)).await {
+            Ok(doc) => doc.is_some(),
+            Err(e) => {
+                error!("Failed to get report of [{}] from DB : {:?}", id, e);
+                // Better not report than report twice
+                true
+            }
+        }
+    }
+
+    async fn has_budget(&self) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let today = now - now % 86400;
+        match self.storage.count::<Report>(Filter::gte("date", today)).await {
+            Ok(count) => count < self.config.report.daily_budget,
+            Err(e) => {
+                error!("Failed to count today's reports : {:?}", e);
+                false
+            }
+        }
+    }
+
+}
@@ -0,0 +1,148 @@
+use log::{info, error};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use config::{Config, MessageClass, RetentionRule};
+use model::{Message, Record, Report, Sanction};
+use crate::recorder::{Filter, Recorder};
+
+/**
+*   Purges the stored data older than the `[retention]` rules allow
+*/
+pub struct Retention<T: Recorder> {
+    config: Config,
+    recorder: T
+}
+
+impl<T: Recorder> Retention<T> {
+
+    pub fn new(config: Config, recorder: T) -> Self {
+        Retention {
+            config,
+            recorder
+        }
+    }
+
+    pub async fn run(self) {
+        if self.config.retention.rules.is_empty() {
+            return;
+        }
+        loop {
+            self.apply().await;
+            tokio::time::sleep(Duration::from_secs(self.config.retention.period)).await;
+        }
+    }
+
+    /**
+    *   Applies every rule once, returns how many documents were purged, or would be in dry-run
+    */
+    pub async fn apply(&self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let mut purged = 0;
+        for rule in self.config.retention.rules.iter() {
+            let result = match rule.collection.as_str() {
+                collection if collection == model::MESSAGES_COLLECTION => self.purge::<Message>(rule, now).await,
+                collection if collection == model::STATS_COLLECTION => self.purge::<Sanction>(rule, now).await,
+                collection if collection == model::REPORTS_COLLECTION => self.purge::<Report>(rule, now).await,
+                collection => {
+                    error!("No retention possible on '{}', its documents aren't dated", collection);
+                    continue;
+                }
+            };
+            match result {
+                Ok(count) => purged += count,
+                Err(e) => error!("Failed to apply retention rule on '{}' : {:?}", rule.collection, e)
+            }
+        }
+        purged
+    }
+
+    async fn purge<R: Record>(&self, rule: &RetentionRule, now: i64) -> Result<u64, T::Error> {
+        let mut filter = Filter::lt("date", now - (rule.days * 24 * 3600) as i64);
+        if let Some(class) = rule.class {
+            filter = filter.and(Filter::eq("trigger", class == MessageClass::Scam));
+        }
+        let target = match &rule.field {
+            Some(field) => format!("'{}' of", field),
+            None => String::from("whole")
+        };
+
+        if self.config.retention.dry_run {
+            let filter = match &rule.field {
+                Some(field) => filter.and(Filter::exists(field, true)),
+                None => filter
+            };
+            let count = self.recorder.count::<R>(filter).await?;
+            info!("[dry-run] Would purge {} {} documents older than {} days from '{}'", target, count, rule.days, rule.collection);
+            return Ok(count);
+        }
+
+        let count = match &rule.field {
+            Some(field) => self.recorder.unset::<R>(filter, field).await?,
+            None => self.recorder.delete_all::<R>(filter).await?
+        };
+        if count > 0 {
+            info!("Purged {} {} documents older than {} days from '{}'", target, count, rule.days, rule.collection);
+        }
+        Ok(count)
+    }
+
+}
+
+// Collections the rules can apply to, their documents have a `date`
+pub fn is_dated(collection: &str) -> bool {
+    [model::MESSAGES_COLLECTION, model::STATS_COLLECTION, model::REPORTS_COLLECTION].contains(&collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use config::{Config, MessageClass, RetentionRule};
+    use model::Message;
+    use mongodb::bson::doc;
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+    use crate::retention::Retention;
+
+    // Messages by id, age in days and whether they were scams
+    fn stored(messages: &[(i64, i64, bool)]) -> Memory {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let memory = Memory::new();
+        for (id, days, trigger) in messages {
+            memory.insert(model::MESSAGES_COLLECTION, doc! {
+                "id": id, "sender": 1_i64, "chat_id": -1_i64, "date": now - days * 24 * 3600, "edit_date": 0_i64,
+                "restriction_reason": "", "type": "MessageText", "content": "hello", "is_scam": false, "trigger": trigger
+            });
+        }
+        memory
+    }
+
+    fn retention(memory: &Memory, dry_run: bool) -> Retention<Memory> {
+        let mut config = Config::empty();
+        config.retention.dry_run = dry_run;
+        config.retention.rules = vec![
+            RetentionRule { collection: model::MESSAGES_COLLECTION.to_string(), class: Some(MessageClass::Clean), field: Some("content".to_string()), days: 7 },
+            RetentionRule { collection: model::MESSAGES_COLLECTION.to_string(), class: Some(MessageClass::Scam), field: None, days: 365 }
+        ];
+        Retention::new(config, memory.clone())
+    }
+
+    #[tokio::test]
+    async fn purges_clean_content_and_old_scams() {
+        let memory = stored(&[(1, 0, false), (2, 10, false), (3, 10, true), (4, 400, true)]);
+
+        assert_eq!(retention(&memory, false).apply().await, 2);
+        assert_eq!(memory.count::<Message>(Filter::All).await.unwrap(), 3);
+        assert_eq!(memory.count::<Message>(Filter::exists("content", false)).await.unwrap(), 1);
+        assert!(memory.get::<Message>((-1, 2)).await.unwrap().is_some());
+        assert_eq!(memory.count::<Message>(Filter::eq("id", 4)).await.unwrap(), 0);
+        assert_eq!(retention(&memory, false).apply().await, 0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_only_counts() {
+        let memory = stored(&[(1, 10, false), (2, 400, true)]);
+
+        assert_eq!(retention(&memory, true).apply().await, 2);
+        assert_eq!(memory.count::<Message>(Filter::exists("content", true)).await.unwrap(), 2);
+    }
+
+}
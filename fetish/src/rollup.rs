@@ -0,0 +1,143 @@
+use log::{info, error};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use config::Config;
+use model::{Counts, Dimension, Message, Rollup, Sanction, ScamType};
+use crate::recorder::{Filter, Recorder};
+
+/**
+*   Computes the daily rollups from the stored messages and sanctions
+*/
+pub struct Rollups<T: Recorder> {
+    config: Config,
+    recorder: T
+}
+
+impl<T: Recorder> Rollups<T> {
+
+    pub fn new(config: Config, recorder: T) -> Self {
+        Rollups {
+            config,
+            recorder
+        }
+    }
+
+    pub async fn run(self) {
+        loop {
+            let today = model::day_of(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64);
+            // Yesterday's warnings may have been delivered after its last computation
+            for day in [today - model::DAY_SECONDS, today].iter() {
+                match self.roll(*day).await {
+                    Ok(count) => info!("{} rollups computed for day {}", count, day),
+                    Err(e) => error!("Failed to compute the rollups of day {} : {:?}", day, e)
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(self.config.stats.period)).await;
+        }
+    }
+
+    /**
+    *   Computes and saves the rollups of a day, returns how many were saved
+    */
+    pub async fn roll(&self, day: i64) -> Result<usize, T::Error> {
+        let day = model::day_of(day);
+        let range = Filter::gte("date", day).and(Filter::lt("date", day + model::DAY_SECONDS));
+        let mut counts: HashMap<(Dimension, String), Counts> = HashMap::new();
+
+        // Every stored message went through the analysis
+        for message in self.recorder.find::<Message>(range.clone()).await? {
+            counts.entry((Dimension::Chat, message.chat_id().to_string())).or_default().threats += 1;
+        }
+
+        for sanction in self.recorder.find::<Sanction>(range).await? {
+            // A warning never sent, or failing, was suppressed
+            let sent = sanction.reply_latency().is_some();
+            let outcome = Counts {
+                scams: 1,
+                warnings_sent: sent as i64,
+                warnings_suppressed: !sent as i64,
+                ..Counts::default()
+            };
+            for key in dimensions(&sanction) {
+                counts.entry(key).or_default().add(&outcome);
+            }
+        }
+
+        let rollups: Vec<Rollup> = counts.iter()
+            .map(|((dimension, value), counts)| Rollup::new(day, *dimension, value, *counts))
+            .collect();
+        let count = rollups.len();
+        self.recorder.save_all(rollups).await?;
+        Ok(count)
+    }
+
+}
+
+// Values a sanction is counted in, once each
+fn dimensions(sanction: &Sanction) -> Vec<(Dimension, String)> {
+    let mut keys = vec![(Dimension::Chat, sanction.message().chat_id().to_string())];
+    for scam_type in sanction.scam_types() {
+        let mut found = vec![(Dimension::ScamType, scam_type.name().to_string())];
+        if let ScamType::Keyword { keyword, language } = scam_type {
+            found.push((Dimension::Keyword, keyword.clone()));
+            if !language.is_empty() {
+                found.push((Dimension::Language, language.clone()));
+            }
+        }
+        for key in found {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use config::Config;
+    use model::{Dimension, MessageSeen, Record, Rollup};
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+    use crate::rollup::Rollups;
+
+    static DAY: i64 = 86_400 * 100;
+
+    fn sanction(id: i64, chat_id: i64, actions: &[&str], sent: Option<bool>) -> mongodb::bson::Document {
+        let mut doc = doc! {
+            "id": id, "chat_id": chat_id, "message": id, "date": DAY + id, "actions": actions.to_vec(), "schema_version": 2_i64,
+            "scam-types": [{ "type": "Keyword", "keyword": "ESCORT", "language": "en" }, { "type": "Keyword", "keyword": "WHATSAPP", "language": "en" }]
+        };
+        if let Some(sent) = sent {
+            doc.insert("delivery", doc! { "timestamp": DAY + id, "is_sent": sent, "latency": 5_i64 });
+        }
+        doc
+    }
+
+    #[tokio::test]
+    async fn sanctions_are_counted_by_chat_scam_type_keyword_and_language() {
+        let memory = Memory::new();
+        memory.insert(model::STATS_COLLECTION, sanction(1, -1, &["reply"], Some(true)));
+        memory.insert(model::STATS_COLLECTION, sanction(2, -1, &["reply"], Some(false)));
+        memory.insert(model::STATS_COLLECTION, sanction(3, -2, &["delete"], None));
+        memory.insert(model::STATS_COLLECTION, sanction(86_400, -2, &["reply"], Some(true)));
+        memory.save(&MessageSeen::new(-1, DAY + 1)).await.unwrap();
+        memory.save(&MessageSeen::new(-1, DAY + 2)).await.unwrap();
+
+        let rollups = Rollups::new(Config::empty(), memory.clone());
+        assert_eq!(rollups.roll(DAY + 10).await.unwrap(), 6);
+        // Computing again only refreshes them
+        assert_eq!(rollups.roll(DAY).await.unwrap(), 6);
+
+        let get = |dimension: Dimension, value: &str| memory.get::<Rollup>(Rollup::new(DAY, dimension, value, Default::default()).id());
+        let chat = get(Dimension::Chat, "-1").await.unwrap().unwrap();
+        assert_eq!((chat.counts().messages, chat.counts().scams, chat.counts().warnings_sent, chat.counts().warnings_suppressed), (2, 2, 1, 1));
+        let language = get(Dimension::Language, "en").await.unwrap().unwrap();
+        assert_eq!((language.counts().scams, language.counts().warnings_suppressed), (3, 2));
+        assert_eq!(get(Dimension::Keyword, "WHATSAPP").await.unwrap().unwrap().counts().scams, 3);
+        assert_eq!(get(Dimension::ScamType, "Keyword").await.unwrap().unwrap().counts().scams, 3);
+        assert_eq!(memory.count::<Rollup>(Filter::eq("day", DAY)).await.unwrap(), 6);
+    }
+
+}
@@ -1,36 +1,37 @@
-use log::info;
+use log::{info, error};
 use unidecode::unidecode;
 use rtdlib::types::{Message, MessageContent::*, MessageSender, User};
 use config::Config;
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::mongo::Mongo;
-use model::{Keywords, KeywordMatch, ScamType, Record, ForbiddenNames};
+use model::{ScamType, Key, Record};
 use crate::recorder::Recorder;
+use crate::cache::Cache;
 
 #[derive(Clone)]
 pub struct ScamAnalyser<T: Recorder> {
     config: Config,
-    mongo: T
+    recorder: T,
+    cache: Cache
 }
 
-impl ScamAnalyser<Mongo> {
+impl<T: Recorder> ScamAnalyser<T> {
 
-    pub fn new(config: Config, mongo: Mongo) -> Self {
+    pub fn new(config: Config, recorder: T, cache: Cache) -> Self {
         ScamAnalyser {
             config,
-            mongo
+            recorder,
+            cache
         }
     }
 
-    pub fn is_threat(&self, message: &Message) -> bool {
+    pub async fn is_threat(&self, message: &Message) -> bool {
         if message.is_outgoing() {
             info!("[{}] is an output message", message.id());
             return false;
         }
 
         if let MessageSender::User(message_sender_user) = message.sender() {
-            if let Some(doc) = self.mongo.get_doc(model::USERS_COLLECTION, message_sender_user.user_id()).unwrap() {
-                let user = model::User::from_doc(&doc);
+            if let Some(user) = self.get::<model::User>(message_sender_user.user_id()).await {
                 if user.is_bypass() {
                     info!("[{}] has the n-word pass", user.first_name);
                     return false;
@@ -47,7 +48,7 @@ impl ScamAnalyser<Mongo> {
         true
     }
 
-    pub fn analyse(&self, message: &Message) -> Vec<ScamType> {
+    pub async fn analyse(&self, message: &Message) -> Vec<ScamType> {
         let content = message.content();
         let mut ret = Vec::new();
 
@@ -61,14 +62,16 @@ impl ScamAnalyser<Mongo> {
         // Keyword scam
         if let Some(text) = text {
             let text = text.to_uppercase();
-            if self.is_keyword_in_text(&text) || text.eq("CC") {
-                ret.push(ScamType::Keyword);
+            if let Some(scam_type) = self.keyword_in_text(&text) {
+                ret.push(scam_type);
+            } else if text.eq("CC") {
+                ret.push(ScamType::Keyword { keyword: text, language: String::new() });
             }
         }
 
         // Account scam
         if let MessageSender::User(message_sender) = message.sender() {
-            if self.is_scammer_account(message_sender.user_id()) {
+            if self.is_scammer_account(message_sender.user_id()).await {
                 ret.push(ScamType::Account(message_sender.user_id()));
             }
         }
@@ -76,25 +79,22 @@ impl ScamAnalyser<Mongo> {
         ret
     }
 
-    fn is_keyword_in_text(&self, text: &str) -> bool {
+    fn keyword_in_text(&self, text: &str) -> Option<ScamType> {
         let text = unidecode(text);
 
-        if let KeywordMatch::NoneMatch = Keywords::from_doc(&self.mongo.get_doc(model::CONFIG_COLLECTION, model::KEYWORDS_ID).unwrap().unwrap()).text_match(&text) {
-            false
-        } else {
-            true
-        }
+        self.cache.keywords().and_then(|keywords| keywords.find(&text)
+            .map(|(language, keyword)| ScamType::Keyword { keyword: keyword.clone(), language: language.to_string() }))
     }
 
-    fn is_scammer_account(&self, user_id: i64) -> bool {
-        if let Some(doc) = self.mongo.get_doc(model::USERS_COLLECTION, user_id).unwrap() {
-            model::User::from_doc(&doc).is_scam_by_admin()
+    async fn is_scammer_account(&self, user_id: i64) -> bool {
+        if let Some(user) = self.get::<model::User>(user_id).await {
+            user.is_scam_by_admin()
         } else {
             false
         }
     }
 
-    pub fn is_new_user_scam(&self, user: &User) -> bool {
+    pub async fn is_new_user_scam(&self, user: &User) -> bool {
         let first_name = unidecode(user.first_name());
         let last_name = unidecode(user.last_name());
         let username = unidecode(user.username());
@@ -112,14 +112,33 @@ impl ScamAnalyser<Mongo> {
         // TODO : Same name in DB
 
         // Forbidden names
-        if ForbiddenNames::from_doc(&self.mongo.get_doc(model::CONFIG_COLLECTION, model::FORBIDDEN_NAMES_ID).unwrap().unwrap())
-            .name_match(&first_name, &last_name) {
-            return true;
+        if let Some(forbidden_names) = self.cache.forbidden_names() {
+            if forbidden_names.name_match(&first_name, &last_name) {
+                return true;
+            }
         }
 
         false
     }
 
+    // Scammers rename constantly to escape the lists they're on
+    pub fn is_frequent_renamer(&self, user: &model::User) -> bool {
+        let renames = &self.config.renames;
+        let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().saturating_sub(renames.period);
+        renames.max > 0 && user.renames_since(since as i64) as u64 > renames.max
+    }
+
+    // A record that can't be read is logged and treated as missing
+    async fn get<R: Record>(&self, key: impl Into<Key> + Send) -> Option<R> {
+        match self.recorder.get::<R>(key).await {
+            Ok(record) => record,
+            Err(e) => {
+                error!("Failed to read from '{}' : {:?}", R::collection(), e);
+                None
+            }
+        }
+    }
+
 }
 
 /*
@@ -144,47 +163,129 @@ impl ScamAnalyser<Mongo> {
 }
  */
 
-/*#[cfg(test)]
+#[cfg(test)]
 mod tests {
-    use crate::ScamAnalyser;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use rtdlib::types::{FormattedText, Message, MessageContent, MessageSender, MessageSenderUser, MessageText, User};
+    use config::Config;
+    use model::{ForbiddenNames, Keywords, Merge, ScamType};
+    use crate::cache::Cache;
+    use crate::memory::Memory;
     use crate::recorder::Recorder;
-    use mongodb::bson::Document;
-    use mongodb::error::Error;
-    use model::Record;
-
-    struct MongoMock;
-
-    impl Recorder for MongoMock {
-
-        fn get_doc(&self, collection_name: &str, id: i64) -> Result<Option<Document>, Error> {
-            todo!()
-        }
+    use crate::scam_analyser::ScamAnalyser;
+
+    async fn analyser() -> ScamAnalyser<Memory> {
+        let keywords: Vec<String> = serde_json::from_str(include_str!("../../res/keywords.json")).unwrap();
+        let memory = Memory::new();
+        memory.save(&Keywords::new(keywords, vec![], vec![])).await.unwrap();
+        memory.save(&ForbiddenNames::new(vec![String::from("Mamadou")])).await.unwrap();
+        let cache = Cache::new();
+        cache.load(&memory).await;
+
+        let mut config = Config::empty();
+        config.sender.timeout = 300.0;
+        ScamAnalyser::new(config, memory, cache)
+    }
 
-        fn save_doc(&self, doc: impl Record) -> Result<(), Error> {
-            todo!()
-        }
+    fn message(user_id: i64, text: &str) -> Message {
+        Message::builder()
+            .id(1)
+            .chat_id(-1)
+            .sender(MessageSender::user(MessageSenderUser::builder().user_id(user_id).build()))
+            .date(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64)
+            .content(MessageContent::message_text(MessageText::builder().text(FormattedText::builder().text(text).build()).build()))
+            .build()
+    }
 
+    fn user(id: i64, first_name: &str, last_name: &str, username: &str) -> User {
+        User::builder()
+            .id(id)
+            .first_name(first_name)
+            .last_name(last_name)
+            .username(username)
+            .build()
     }
 
-    #[test]
-    fn test1() {
+    #[tokio::test]
+    async fn test1() {
         let message = "Coucou je suis disponible pour des rencontres coquines intéressé veuillez me contacter 💯💯💞💯💞";
 
-        assert!(is_keyword_in_text(message));
+        assert!(analyser().await.keyword_in_text(message).is_some());
     }
 
-    #[test]
-    fn test2() {
+    #[tokio::test]
+    async fn test2() {
         let message = "Salut ici je suis disponible pour des plans cul et sexcam si tu es intéressé écrit moi en privé";
 
-        assert!(is_keyword_in_text(message));
+        assert!(analyser().await.keyword_in_text(message).is_some());
     }
 
-    #[test]
-    fn test3() {
+    #[tokio::test]
+    async fn test3() {
         let message = "Iks creent tous des snap mtn";
 
-        assert!(!is_keyword_in_text(message));
+        assert!(analyser().await.keyword_in_text(message).is_none());
+    }
+
+    #[tokio::test]
+    async fn keyword_message_is_a_scam() {
+        let analyser = analyser().await;
+        let message = message(42, "Je suis dispo sans prise de tête, écris moi sur WhatsApp");
+
+        assert!(analyser.is_threat(&message).await);
+        assert!(matches!(analyser.analyse(&message).await.as_slice(), [ScamType::Keyword { .. }]));
+    }
+
+    #[tokio::test]
+    async fn clean_message_is_not_a_scam() {
+        let analyser = analyser().await;
+
+        assert!(analyser.analyse(&message(42, "On se retrouve demain au match ?")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn scammer_account_is_a_scam() {
+        let analyser = analyser().await;
+        analyser.recorder.save(&model::User::flagged(42)).await.unwrap();
+
+        assert!(matches!(analyser.analyse(&message(42, "Bonjour")).await.as_slice(), [ScamType::Account(42)]));
+        assert!(analyser.analyse(&message(43, "Bonjour")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn old_message_is_not_a_threat() {
+        let mut analyser = analyser().await;
+        analyser.config.sender.timeout = 0.0;
+
+        assert!(!analyser.is_threat(&message(42, "Je suis dispo")).await);
     }
 
-}*/
+    #[tokio::test]
+    async fn new_user_scam() {
+        let analyser = analyser().await;
+
+        assert!(analyser.is_new_user_scam(&user(1, "Julie", "Julie", "")).await);
+        assert!(analyser.is_new_user_scam(&user(2, "Julie", "Martin", "julie_escort")).await);
+        assert!(analyser.is_new_user_scam(&user(3, "Mamadou", "Diallo", "")).await);
+        assert!(!analyser.is_new_user_scam(&user(4, "Julie", "Martin", "julie_m")).await);
+    }
+
+    #[tokio::test]
+    async fn frequent_renamer_is_a_scam() {
+        let mut analyser = analyser().await;
+        analyser.config.renames.max = 2;
+        let mut stored = model::User::from_td(&user(5, "Julie", "Martin", "julie"), false);
+
+        for name in ["Sarah", "Emma"].iter() {
+            stored.merge(&model::User::from_td(&user(5, name, "Martin", "julie"), false));
+        }
+        assert!(!analyser.is_frequent_renamer(&stored));
+
+        stored.merge(&model::User::from_td(&user(5, "Lea", "Martin", "julie"), false));
+        assert!(analyser.is_frequent_renamer(&stored));
+
+        analyser.config.renames.max = 0;
+        assert!(!analyser.is_frequent_renamer(&stored));
+    }
+
+}
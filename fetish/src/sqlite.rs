@@ -0,0 +1,371 @@
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use log::info;
+use mongodb::bson::{Bson, Document};
+use rusqlite::{params, params_from_iter, Connection, Error, OptionalExtension, ToSql};
+use rusqlite::types::ToSqlOutput;
+use model::{DecodeError, Key, Record};
+use crate::recorder::{apply_update, outdated, upgrade_doc, Filter, Recorder, RecorderError, Value};
+
+type Migration = fn(&Connection) -> Result<(), Error>;
+
+/**
+*   Schema migrations, the database's `user_version` is the amount of migrations already applied.
+*   New migrations are appended, never edited once released.
+*/
+static MIGRATIONS: &[Migration] = &[
+    create_collections,
+    create_rollups,
+    create_audit
+];
+
+/**
+*   Recorder keeping the records in a SQLite file.
+*   Each collection is a table of JSON documents, keyed by the record's chat id (0 for `Key::Id`) and id.
+*   Queries on the local file are short enough to run right on the calling task.
+*/
+#[derive(Clone)]
+pub struct Sqlite {
+    conn: Arc<Mutex<Connection>>
+}
+
+impl Sqlite {
+
+    pub fn open(path: &str) -> Result<Self, Error> {
+        info!("Opening SQLite database '{}'", path);
+        Sqlite::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, Error> {
+        Sqlite::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, Error> {
+        migrate(&mut conn)?;
+        Ok(Sqlite {
+            conn: Arc::new(Mutex::new(conn))
+        })
+    }
+
+}
+
+#[async_trait]
+impl Recorder for Sqlite {
+
+    type Error = RecorderError<Error>;
+
+    async fn get<T: Record>(&self, key: impl Into<Key> + Send) -> Result<Option<T>, Self::Error> {
+        let (chat_id, id) = key_columns(key.into());
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn.query_row(
+            &format!("SELECT doc FROM {} WHERE chat_id = ?1 AND id = ?2", T::collection()),
+            params![chat_id, id],
+            |row| row.get(0)
+        ).optional()?;
+        json.map(|json| T::from_doc(&from_json(T::collection(), &json)?))
+            .transpose()
+            .map_err(RecorderError::Decode)
+    }
+
+    async fn find<T: Record>(&self, filter: Filter) -> Result<Vec<T>, Self::Error> {
+        let mut values = Vec::new();
+        let clause = filter_to_sql(&filter, &mut values);
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!("SELECT doc FROM {} WHERE {}", T::collection(), clause))?;
+        let records = statement.query_map(params_from_iter(values.iter()), |row| row.get::<_, String>(0))?
+            .map(|json| from_json(T::collection(), &json?).and_then(|doc| T::from_doc(&doc)).map_err(RecorderError::Decode))
+            .collect();
+        records
+    }
+
+    async fn count<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let mut values = Vec::new();
+        let clause = filter_to_sql(&filter, &mut values);
+        let conn = self.conn.lock().unwrap();
+        let count = conn.query_row(
+            &format!("SELECT COUNT(*) FROM {} WHERE {}", T::collection(), clause),
+            params_from_iter(values.iter()),
+            |row| row.get::<_, i64>(0)
+        )?;
+        Ok(count as u64)
+    }
+
+    async fn save(&self, model: &impl Record) -> Result<(), Self::Error> {
+        let (chat_id, id) = key_columns(model.key());
+        let conn = self.conn.lock().unwrap();
+        let stored: Option<String> = conn.query_row(
+            &format!("SELECT doc FROM {} WHERE chat_id = ?1 AND id = ?2", model.collection_name()),
+            params![chat_id, id],
+            |row| row.get(0)
+        ).optional()?;
+
+        let doc = match stored {
+            Some(json) => {
+                info!("Updating {:?} in '{}'", model.key(), model.collection_name());
+                let mut doc = from_json(collection_of(model), &json).map_err(RecorderError::Decode)?;
+                apply_update(&mut doc, &model.to_doc_update());
+                doc
+            },
+            None => {
+                info!("Saving {:?} in '{}'", model.key(), model.collection_name());
+                model.to_doc()
+            }
+        };
+
+        conn.execute(
+            &format!("INSERT OR REPLACE INTO {} (chat_id, id, doc) VALUES (?1, ?2, ?3)", model.collection_name()),
+            params![chat_id, id, to_json(doc)]
+        )?;
+        Ok(())
+    }
+
+    async fn save_all<T: Record>(&self, models: Vec<T>) -> Result<(), Self::Error> {
+        for model in models {
+            self.save(&model).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete<T: Record>(&self, key: impl Into<Key> + Send) -> Result<bool, Self::Error> {
+        let key = key.into();
+        let (chat_id, id) = key_columns(key);
+        info!("Deleting {:?} from '{}'", key, T::collection());
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(&format!("DELETE FROM {} WHERE chat_id = ?1 AND id = ?2", T::collection()), params![chat_id, id])?;
+        Ok(deleted > 0)
+    }
+
+    async fn delete_all<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let mut values = Vec::new();
+        let clause = filter_to_sql(&filter, &mut values);
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(&format!("DELETE FROM {} WHERE {}", T::collection(), clause), params_from_iter(values.iter()))?;
+        Ok(deleted as u64)
+    }
+
+    async fn unset<T: Record>(&self, filter: Filter, field: &str) -> Result<u64, Self::Error> {
+        let mut values = Vec::new();
+        values.push(json_path(field));
+        let clause = filter_to_sql(&filter.and(Filter::exists(field, true)), &mut values);
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            &format!("UPDATE {} SET doc = json_remove(doc, ?) WHERE {}", T::collection(), clause),
+            params_from_iter(values.iter())
+        )?;
+        Ok(updated as u64)
+    }
+
+    async fn upgrade<T: Record>(&self, filter: Filter) -> Result<u64, Self::Error> {
+        let mut values = Vec::new();
+        let clause = filter_to_sql(&filter.and(outdated::<T>()), &mut values);
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.prepare(&format!("SELECT chat_id, id, doc FROM {} WHERE {}", T::collection(), clause))?
+            .query_map(params_from_iter(values.iter()), |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for (chat_id, id, json) in &rows {
+            let mut doc = from_json(T::collection(), json).map_err(RecorderError::Decode)?;
+            upgrade_doc::<T>(&mut doc);
+            conn.execute(
+                &format!("UPDATE {} SET doc = ?3 WHERE chat_id = ?1 AND id = ?2", T::collection()),
+                params![chat_id, id, to_json(doc)]
+            )?;
+        }
+        Ok(rows.len() as u64)
+    }
+
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(match self {
+            Value::Int(i) => ToSqlOutput::from(*i),
+            // JSON booleans are extracted as 0 and 1
+            Value::Bool(b) => ToSqlOutput::from(*b),
+            Value::Str(s) => ToSqlOutput::from(s.as_str())
+        })
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let version = conn.pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))? as usize;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = conn.transaction()?;
+        migration(&transaction)?;
+        transaction.pragma_update(None, "user_version", &((index + 1) as i64))?;
+        transaction.commit()?;
+        info!("SQLite schema migrated to version {}", index + 1);
+    }
+    Ok(())
+}
+
+fn create_collections(conn: &Connection) -> Result<(), Error> {
+    let collections = [
+        model::USERS_COLLECTION,
+        model::MESSAGES_COLLECTION,
+        model::CHATS_COLLECTION,
+        model::CONFIG_COLLECTION,
+        model::STATS_COLLECTION,
+        model::REPORTS_COLLECTION,
+        model::WARNINGS_COLLECTION
+    ];
+
+    for collection in collections.iter() {
+        create_collection(conn, collection)?;
+    }
+    Ok(())
+}
+
+fn create_rollups(conn: &Connection) -> Result<(), Error> {
+    create_collection(conn, model::ROLLUPS_COLLECTION)
+}
+
+fn create_audit(conn: &Connection) -> Result<(), Error> {
+    create_collection(conn, model::AUDIT_COLLECTION)
+}
+
+fn create_collection(conn: &Connection, collection: &str) -> Result<(), Error> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (chat_id INTEGER NOT NULL, id INTEGER NOT NULL, doc TEXT NOT NULL, PRIMARY KEY (chat_id, id))",
+        collection
+    ))
+}
+
+fn key_columns(key: Key) -> (i64, i64) {
+    match key {
+        Key::Id(id) => (0, id),
+        Key::Chat(chat_id, id) => (chat_id, id)
+    }
+}
+
+fn to_json(doc: Document) -> String {
+    Bson::Document(doc).into_relaxed_extjson().to_string()
+}
+
+// A stored row that isn't a JSON document can't be decoded, like a field of the wrong type
+fn from_json(collection: &'static str, json: &str) -> Result<Document, DecodeError> {
+    let error = || DecodeError::WrongType { collection, field: String::from("doc"), expected: "JSON document" };
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|_| error())?;
+    match widen(Bson::try_from(value).map_err(|_| error())?) {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(error())
+    }
+}
+
+// The records saved are only known by reference, their collection is the type's
+fn collection_of<T: Record>(_: &T) -> &'static str {
+    T::collection()
+}
+
+// JSON doesn't keep the integers' width and small ones come back as Int32, the records only use Int64
+fn widen(bson: Bson) -> Bson {
+    match bson {
+        Bson::Int32(i) => Bson::Int64(i as i64),
+        Bson::Array(array) => Bson::Array(array.into_iter().map(widen).collect()),
+        Bson::Document(doc) => Bson::Document(doc.into_iter().map(|(key, value)| (key, widen(value))).collect()),
+        other => other
+    }
+}
+
+// Field names can come from the command line or the config, they're bound as JSON paths like the values
+fn json_path(name: &str) -> Value {
+    Value::Str(format!("$.{}", name))
+}
+
+fn filter_to_sql(filter: &Filter, values: &mut Vec<Value>) -> String {
+    let mut compare = |name: &str, operator: &str, value: &Value| {
+        values.push(json_path(name));
+        values.push(value.clone());
+        format!("json_extract(doc, ?) {} ?", operator)
+    };
+
+    match filter {
+        Filter::All => String::from("1"),
+        Filter::Eq(name, value) => compare(name, "=", value),
+        Filter::Gt(name, value) => compare(name, ">", value),
+        Filter::Gte(name, value) => compare(name, ">=", value),
+        Filter::Lt(name, value) => compare(name, "<", value),
+        Filter::Lte(name, value) => compare(name, "<=", value),
+        Filter::In(_, list) if list.is_empty() => String::from("0"),
+        Filter::In(name, list) => {
+            values.push(json_path(name));
+            values.extend(list.iter().cloned());
+            format!("json_extract(doc, ?) IN ({})", vec!["?"; list.len()].join(", "))
+        },
+        Filter::Exists(name, exists) => {
+            values.push(json_path(name));
+            format!("json_type(doc, ?) IS {}", if *exists { "NOT NULL" } else { "NULL" })
+        },
+        Filter::And(filters) if filters.is_empty() => String::from("1"),
+        Filter::And(filters) => format!("({})", filters.iter().map(|filter| filter_to_sql(filter, values)).collect::<Vec<String>>().join(" AND ")),
+        Filter::Or(filters) if filters.is_empty() => String::from("0"),
+        Filter::Or(filters) => format!("({})", filters.iter().map(|filter| filter_to_sql(filter, values)).collect::<Vec<String>>().join(" OR "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{Key, Record, User, Warning};
+    use crate::recorder::{Filter, Recorder, RecorderError};
+    use crate::recorder::tests::check_recorder;
+    use crate::sqlite::Sqlite;
+
+    #[tokio::test]
+    async fn behaves_like_a_recorder() {
+        check_recorder(Sqlite::in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn schema_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("fetish-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        Sqlite::open(path).unwrap().save(&Warning::new(10, -1, 5, 0)).await.unwrap();
+        let reopened = Sqlite::open(path).unwrap();
+        let version: i64 = reopened.conn.lock().unwrap().pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+
+        assert_eq!(version as usize, super::MIGRATIONS.len());
+        assert!(reopened.get::<Warning>(Key::Chat(-1, 10)).await.unwrap().is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn upgrade_rewrites_outdated_documents() {
+        let sqlite = Sqlite::in_memory().unwrap();
+        sqlite.conn.lock().unwrap().execute(
+            "INSERT INTO users (chat_id, id, doc) VALUES (0, 1, '{\"id\": 1, \"first_name\": \"Alice\"}')",
+            rusqlite::params![]
+        ).unwrap();
+
+        assert_eq!(sqlite.upgrade::<User>(Filter::All).await.unwrap(), 1);
+        assert_eq!(sqlite.upgrade::<User>(Filter::All).await.unwrap(), 0);
+        assert_eq!(sqlite.count::<User>(Filter::eq("scam", false)).await.unwrap(), 1);
+        let user = sqlite.get::<User>(1).await.unwrap().unwrap();
+        assert_eq!(user.to_doc().get_i64(model::SCHEMA_VERSION).unwrap(), User::SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn rows_that_arent_documents_fail_to_decode() {
+        let sqlite = Sqlite::in_memory().unwrap();
+        sqlite.conn.lock().unwrap().execute("INSERT INTO users (chat_id, id, doc) VALUES (0, 1, '[1, 2]')", rusqlite::params![]).unwrap();
+
+        assert!(matches!(sqlite.get::<User>(1).await, Err(RecorderError::Decode(_))));
+        assert!(matches!(sqlite.upgrade::<User>(Filter::All).await, Err(RecorderError::Decode(_))));
+    }
+
+    #[tokio::test]
+    async fn hostile_field_names_stay_in_their_json_path() {
+        let sqlite = Sqlite::in_memory().unwrap();
+        sqlite.save_all(vec![Warning::new(1, -1, 5, 0), Warning::new(2, -1, 6, 60)]).await.unwrap();
+        let hostile = "x') IS NULL OR 1 = 1 OR json_type(doc, '$.x";
+
+        assert!(sqlite.count::<Warning>(Filter::exists(hostile, true)).await.map_or(true, |count| count == 0));
+        assert!(sqlite.delete_all::<Warning>(Filter::eq(hostile, 1)).await.map_or(true, |count| count == 0));
+        assert!(sqlite.unset::<Warning>(Filter::All, hostile).await.map_or(true, |count| count == 0));
+        assert_eq!(sqlite.count::<Warning>(Filter::exists("reply_to", true)).await.unwrap(), 2);
+    }
+
+}
@@ -0,0 +1,149 @@
+use async_trait::async_trait;
+use config::{Backend, Config};
+use model::{DecodeError, Key, Record};
+use crate::mongo::Mongo;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::Sqlite;
+use crate::recorder::{Filter, Recorder, RecorderError};
+
+/**
+*   Recorder selected by the `[storage]` section of the config file
+*/
+#[derive(Clone)]
+pub enum Storage {
+    Mongo(Box<Mongo>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Sqlite)
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Mongo(mongodb::error::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    Decode(DecodeError),
+    // Backend left out of the build
+    #[cfg(not(feature = "sqlite"))]
+    Unsupported(&'static str)
+}
+
+impl From<RecorderError<mongodb::error::Error>> for StorageError {
+    fn from(error: RecorderError<mongodb::error::Error>) -> Self {
+        match error {
+            RecorderError::Backend(error) => StorageError::Mongo(error),
+            RecorderError::Decode(error) => StorageError::Decode(error)
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<RecorderError<rusqlite::Error>> for StorageError {
+    fn from(error: RecorderError<rusqlite::Error>) -> Self {
+        match error {
+            RecorderError::Backend(error) => StorageError::Sqlite(error),
+            RecorderError::Decode(error) => StorageError::Decode(error)
+        }
+    }
+}
+
+impl Storage {
+
+    pub async fn new(config: Config) -> Result<Self, StorageError> {
+        match config.storage.backend {
+            Backend::Mongo => Ok(Storage::Mongo(Box::new(Mongo::new(config).await.map_err(StorageError::Mongo)?))),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Ok(Storage::Sqlite(Sqlite::open(config.storage.path.as_str()).map_err(StorageError::Sqlite)?)),
+            #[cfg(not(feature = "sqlite"))]
+            Backend::Sqlite => Err(StorageError::Unsupported("SQLite storage requires building with the 'sqlite' feature"))
+        }
+    }
+
+    // Mongo only features, e.g. change streams
+    pub fn as_mongo(&self) -> Option<&Mongo> {
+        match self {
+            Storage::Mongo(mongo) => Some(mongo),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(_) => None
+        }
+    }
+
+}
+
+#[async_trait]
+impl Recorder for Storage {
+
+    type Error = StorageError;
+
+    async fn get<T: Record>(&self, key: impl Into<Key> + Send) -> Result<Option<T>, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.get(key).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.get(key).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn find<T: Record>(&self, filter: Filter) -> Result<Vec<T>, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.find(filter).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.find(filter).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn count<T: Record>(&self, filter: Filter) -> Result<u64, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.count::<T>(filter).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.count::<T>(filter).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn save(&self, record: &impl Record) -> Result<(), StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.save(record).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.save(record).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn save_all<T: Record>(&self, records: Vec<T>) -> Result<(), StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.save_all(records).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.save_all(records).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn delete<T: Record>(&self, key: impl Into<Key> + Send) -> Result<bool, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.delete::<T>(key).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.delete::<T>(key).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn delete_all<T: Record>(&self, filter: Filter) -> Result<u64, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.delete_all::<T>(filter).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.delete_all::<T>(filter).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn unset<T: Record>(&self, filter: Filter, field: &str) -> Result<u64, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.unset::<T>(filter, field).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.unset::<T>(filter, field).await.map_err(StorageError::from)
+        }
+    }
+
+    async fn upgrade<T: Record>(&self, filter: Filter) -> Result<u64, StorageError> {
+        match self {
+            Storage::Mongo(mongo) => mongo.upgrade::<T>(filter).await.map_err(StorageError::from),
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite(sqlite) => sqlite.upgrade::<T>(filter).await.map_err(StorageError::from)
+        }
+    }
+
+}
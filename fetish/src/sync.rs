@@ -0,0 +1,196 @@
+use mongodb::bson::Bson;
+use serde_json::Value as Json;
+use model::{ForbiddenNames, Keywords, Record};
+use crate::audit::{self, Signature};
+use crate::recorder::Recorder;
+
+static LANGUAGES: [&str; 3] = ["fr", "en", "de"];
+
+// Shorter words would match most of the clean messages
+static MIN_LENGTH: usize = 3;
+
+/**
+*   Words added to and removed from a stored list
+*/
+#[derive(Debug, PartialEq)]
+pub struct Diff {
+    pub list: &'static str,
+    pub added: Vec<String>,
+    pub removed: Vec<String>
+}
+
+impl Diff {
+
+    fn new(list: &'static str, old: &[String], new: &[String]) -> Self {
+        Diff {
+            list,
+            added: new.iter().filter(|word| !old.contains(word)).cloned().collect(),
+            removed: old.iter().filter(|word| !new.contains(word)).cloned().collect()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+}
+
+/**
+*   Reads a keywords file, either a flat list of keywords in `language`, or an object with `fr`, `en` and `de` lists
+*/
+pub fn parse_keywords(json: &str, language: &str) -> Result<Keywords, String> {
+    let json: Json = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+
+    match &json {
+        Json::Array(_) => match LANGUAGES.iter().position(|known| *known == language) {
+            Some(index) => lists[index] = words(&json, LANGUAGES[index])?,
+            None => return Err(format!("Unknown language '{}', expected one of {}", language, LANGUAGES.join(", ")))
+        },
+        Json::Object(object) => {
+            if let Some(unknown) = object.keys().find(|key| !LANGUAGES.contains(&key.as_str())) {
+                return Err(format!("Unknown language '{}', expected one of {}", unknown, LANGUAGES.join(", ")));
+            }
+            for (index, language) in LANGUAGES.iter().enumerate() {
+                if let Some(list) = object.get(*language) {
+                    lists[index] = words(list, language)?;
+                }
+            }
+        },
+        _ => return Err(String::from("Expected a list of keywords, or an object with 'fr', 'en' and 'de' lists"))
+    }
+
+    let [fr, en, de] = lists;
+    Ok(Keywords::new(fr, en, de))
+}
+
+/**
+*   Reads a forbidden names file, either a flat list or an object with a `names` list
+*/
+pub fn parse_forbidden_names(json: &str) -> Result<ForbiddenNames, String> {
+    let json: Json = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let list = match &json {
+        Json::Object(object) => object.get("names").ok_or_else(|| String::from("Missing the 'names' list"))?,
+        other => other
+    };
+    Ok(ForbiddenNames::new(words(list, "names")?))
+}
+
+// Every invalid word is reported at once, so the file is fixed in one go
+fn words(json: &Json, list: &str) -> Result<Vec<String>, String> {
+    let array = json.as_array().ok_or_else(|| format!("'{}' isn't a list", list))?;
+    let (mut words, mut errors) = (Vec::new(), Vec::new());
+
+    for (index, word) in array.iter().enumerate() {
+        match word.as_str().map(|word| word.trim().to_uppercase()) {
+            None => errors.push(format!("'{}' #{} isn't a string", list, index + 1)),
+            Some(word) if word.chars().count() < MIN_LENGTH => errors.push(format!("'{}' #{} '{}' is shorter than {} characters", list, index + 1, word, MIN_LENGTH)),
+            Some(word) if words.contains(&word) => errors.push(format!("'{}' #{} '{}' is a duplicate", list, index + 1, word)),
+            Some(word) => words.push(word)
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(words),
+        false => Err(errors.join("\n"))
+    }
+}
+
+/**
+*   Compares the keywords to the stored ones and saves them when they differ, with an audit entry signed by `signature`.
+*   Without a signature, it's a dry run.
+*/
+pub async fn sync_keywords<R: Recorder>(recorder: &R, keywords: Keywords, signature: Option<&Signature>) -> Result<Vec<Diff>, String> {
+    let current = recorder.get::<Keywords>(model::KEYWORDS_ID).await.map_err(|e| format!("Failed to get keywords : {:?}", e))?;
+    let empty = Vec::new();
+    let diffs = keywords.languages().iter()
+        .enumerate()
+        .map(|(index, (language, new))| {
+            let old = current.as_ref().map_or(&empty, |current| current.languages()[index].1);
+            Diff::new(language, old, new)
+        })
+        .collect::<Vec<Diff>>();
+
+    save(recorder, &keywords, signature).await?;
+    Ok(diffs)
+}
+
+/**
+*   Compares the names to the stored ones and saves them when they differ, like `sync_keywords`
+*/
+pub async fn sync_forbidden_names<R: Recorder>(recorder: &R, names: ForbiddenNames, signature: Option<&Signature>) -> Result<Vec<Diff>, String> {
+    let current = recorder.get::<ForbiddenNames>(model::FORBIDDEN_NAMES_ID).await.map_err(|e| format!("Failed to get forbidden names : {:?}", e))?;
+    let old = current.as_ref().map_or_else(Vec::new, |current| current.names().clone());
+    let diffs = vec![Diff::new("names", &old, names.names())];
+
+    save(recorder, &names, signature).await?;
+    Ok(diffs)
+}
+
+// Nothing is saved when the stored document is the same
+async fn save<R: Recorder>(recorder: &R, record: &impl Record, signature: Option<&Signature>) -> Result<(), String> {
+    match signature {
+        Some(signature) => audit::change(recorder, model::CONFIG_COLLECTION, record.id(), model::WHOLE_DOCUMENT, Bson::Document(record.to_doc()), signature).await.map(|_| ()),
+        None => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{ForbiddenNames, Keywords, KeywordMatch};
+    use model::Audit;
+    use crate::audit::Signature;
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+    use crate::sync::{parse_forbidden_names, parse_keywords, sync_forbidden_names, sync_keywords, Diff};
+
+    #[test]
+    fn both_keyword_formats_are_read() {
+        let flat = parse_keywords(r#"["escort", " Western Union "]"#, "en").unwrap();
+        let split = parse_keywords(r#"{"fr": ["coquine"], "de": ["geld"]}"#, "fr").unwrap();
+
+        assert!(matches!(flat.text_match("Send it by WESTERN UNION"), KeywordMatch::EnMatch));
+        assert!(matches!(split.text_match("Une coquine"), KeywordMatch::FrMatch));
+        assert!(matches!(split.text_match("Viel Geld"), KeywordMatch::DeMatch));
+        assert!(parse_keywords(r#"["escort"]"#, "es").is_err());
+        assert!(parse_keywords(r#"{"es": ["dinero"]}"#, "fr").is_err());
+    }
+
+    #[test]
+    fn invalid_words_are_all_reported() {
+        let error = parse_keywords(r#"["escort", 42, "ab", "ESCORT"]"#, "fr").err().unwrap();
+
+        assert_eq!(error.lines().count(), 3);
+        assert!(parse_forbidden_names(r#"{"list": ["DIALLO"]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn sync_shows_the_diff_and_saves_once() {
+        let memory = Memory::new();
+        memory.save(&Keywords::new(vec![String::from("COQUINE")], vec![String::from("ESCORT")], vec![])).await.unwrap();
+        let keywords = parse_keywords(r#"{"fr": ["coquine", "plan cul"], "en": []}"#, "fr").unwrap();
+
+        let diffs = sync_keywords(&memory, keywords, None).await.unwrap();
+        assert_eq!(diffs[0], Diff { list: "fr", added: vec![String::from("PLAN CUL")], removed: vec![] });
+        assert_eq!(diffs[1], Diff { list: "en", added: vec![], removed: vec![String::from("ESCORT")] });
+        assert!(diffs[2].is_empty());
+
+        let signature = Signature::new(Some("alice"), Some("New list")).unwrap();
+        let keywords = parse_keywords(r#"{"fr": ["coquine", "plan cul"], "en": []}"#, "fr").unwrap();
+        assert!(!sync_keywords(&memory, keywords, Some(&signature)).await.unwrap()[0].is_empty());
+        let keywords = parse_keywords(r#"{"fr": ["coquine", "plan cul"], "en": []}"#, "fr").unwrap();
+        assert!(sync_keywords(&memory, keywords, Some(&signature)).await.unwrap().iter().all(Diff::is_empty));
+        assert_eq!(memory.count::<Audit>(Filter::All).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn forbidden_names_are_created() {
+        let memory = Memory::new();
+        let names = parse_forbidden_names(r#"["Diallo", "Mamadou"]"#).unwrap();
+
+        let signature = Signature::new(Some("alice"), Some("First list")).unwrap();
+        assert_eq!(sync_forbidden_names(&memory, names, Some(&signature)).await.unwrap()[0].added.len(), 2);
+        assert!(memory.get::<ForbiddenNames>(model::FORBIDDEN_NAMES_ID).await.unwrap().unwrap().name_match("", "diallo"));
+    }
+
+}
@@ -5,11 +5,12 @@ use log::debug;
 use telegram_client::api::aevent::EventApi;
 
 use crate::thelp;
+use crate::redact::Sensitive;
 
 pub fn type_phone_number(api: &EventApi) {
     let input = thelp::typed();
     api.set_authentication_phone_number(SetAuthenticationPhoneNumber::builder().phone_number(&input).build()).unwrap();
-    debug!("Set phone number [{}] {}", input.green(), "(If you copy log to anywhere, don't forget hide your phone number)".red());
+    debug!("Set phone number [{}] {}", Sensitive(&input).to_string().green(), "(If you copy log to anywhere, don't forget hide your phone number)".red());
 }
 
 pub fn type_authentication_code(api: &EventApi) {
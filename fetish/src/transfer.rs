@@ -0,0 +1,257 @@
+use std::convert::TryFrom;
+use std::io::{BufRead, Read, Write};
+use mongodb::bson::{Bson, Document};
+use model::{Audit, Chat, ForbiddenNames, Keywords, Message, Record, Rollup, Sanction, User};
+use crate::recorder::{upgrade_doc, Filter, Recorder};
+
+// Collections that can be exported and imported
+pub static COLLECTIONS: [&str; 7] = ["users", "messages", "chats", "stats", "rollups", "audit", "config"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // One JSON document per line
+    Jsonl,
+    // One column per field, values that aren't plain strings are written as JSON
+    Csv
+}
+
+impl Format {
+
+    pub fn from(name: &str) -> Result<Self, String> {
+        match name {
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            other => Err(format!("Unknown format '{}', expected 'jsonl' or 'csv'", other))
+        }
+    }
+
+    pub fn from_path(path: &str) -> Self {
+        match path.ends_with(".csv") {
+            true => Format::Csv,
+            false => Format::Jsonl
+        }
+    }
+
+}
+
+/**
+*   Field the documents of a collection are dated by, none for the users and the config
+*/
+pub fn date_field(collection: &str) -> Option<&'static str> {
+    match collection {
+        "messages" | "stats" | "audit" => Some("date"),
+        "rollups" => Some("day"),
+        // Chats are dated by their last message
+        "chats" => Some("last_activity"),
+        _ => None
+    }
+}
+
+/**
+*   Writes the records of a collection matching the filter, returns how many were written.
+*   Encrypted fields stay encrypted.
+*/
+pub async fn export<R: Recorder>(recorder: &R, collection: &str, filter: Filter, format: Format, writer: impl Write) -> Result<usize, String> {
+    let docs = match collection {
+        "users" => docs::<R, User>(recorder, filter).await?,
+        "messages" => docs::<R, Message>(recorder, filter).await?,
+        "chats" => docs::<R, Chat>(recorder, filter).await?,
+        "stats" => docs::<R, Sanction>(recorder, filter).await?,
+        "rollups" => docs::<R, Rollup>(recorder, filter).await?,
+        "audit" => docs::<R, Audit>(recorder, filter).await?,
+        "config" => {
+            let mut config = docs::<R, Keywords>(recorder, filter.clone().and(Filter::eq("id", model::KEYWORDS_ID))).await?;
+            config.extend(docs::<R, ForbiddenNames>(recorder, filter.and(Filter::eq("id", model::FORBIDDEN_NAMES_ID))).await?);
+            config
+        },
+        other => return Err(unknown(other))
+    };
+
+    match format {
+        Format::Jsonl => write_jsonl(&docs, writer)?,
+        Format::Csv => write_csv(&docs, writer)?
+    }
+    Ok(docs.len())
+}
+
+/**
+*   Upserts the records read, importing the same file twice changes nothing, returns how many were read
+*/
+pub async fn import<R: Recorder>(recorder: &R, collection: &str, format: Format, reader: impl BufRead) -> Result<usize, String> {
+    let docs = match format {
+        Format::Jsonl => read_jsonl(reader)?,
+        Format::Csv => read_csv(reader)?
+    };
+
+    match collection {
+        "users" => save::<R, User>(recorder, &docs).await?,
+        "messages" => save::<R, Message>(recorder, &docs).await?,
+        "chats" => save::<R, Chat>(recorder, &docs).await?,
+        "stats" => save::<R, Sanction>(recorder, &docs).await?,
+        "rollups" => save::<R, Rollup>(recorder, &docs).await?,
+        "audit" => save::<R, Audit>(recorder, &docs).await?,
+        "config" => for doc in docs.iter() {
+            match doc.get("id").and_then(Bson::as_i64) {
+                Some(id) if id == model::KEYWORDS_ID => save::<R, Keywords>(recorder, std::slice::from_ref(doc)).await?,
+                Some(id) if id == model::FORBIDDEN_NAMES_ID => save::<R, ForbiddenNames>(recorder, std::slice::from_ref(doc)).await?,
+                _ => return Err(format!("Unknown config document {}", doc))
+            }
+        },
+        other => return Err(unknown(other))
+    }
+    Ok(docs.len())
+}
+
+fn unknown(collection: &str) -> String {
+    format!("Unknown collection '{}', expected one of {}", collection, COLLECTIONS.join(", "))
+}
+
+async fn docs<R: Recorder, T: Record>(recorder: &R, filter: Filter) -> Result<Vec<Document>, String> {
+    let records = recorder.find::<T>(filter).await.map_err(|e| format!("Failed to read '{}' : {:?}", T::collection(), e))?;
+    Ok(records.iter().map(Record::to_doc).collect())
+}
+
+async fn save<R: Recorder, T: Record>(recorder: &R, docs: &[Document]) -> Result<(), String> {
+    let records = docs.iter()
+        .map(|doc| {
+            // Files written by older versions are upgraded like the stored documents
+            let mut doc = doc.clone();
+            upgrade_doc::<T>(&mut doc);
+            T::from_doc(&doc)
+        })
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|e| e.to_string())?;
+    recorder.save_all(records).await.map_err(|e| format!("Failed to save '{}' : {:?}", T::collection(), e))
+}
+
+fn write_jsonl(docs: &[Document], mut writer: impl Write) -> Result<(), String> {
+    for doc in docs {
+        writeln!(writer, "{}", Bson::Document(doc.clone()).into_relaxed_extjson()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn read_jsonl(reader: impl BufRead) -> Result<Vec<Document>, String> {
+    reader.lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line = line.map_err(|e| e.to_string())?;
+            let json = serde_json::from_str::<serde_json::Value>(&line).map_err(|e| format!("Line {} : {}", index + 1, e))?;
+            match Bson::try_from(json) {
+                Ok(Bson::Document(doc)) => Ok(doc),
+                _ => Err(format!("Line {} isn't a document", index + 1))
+            }
+        })
+        .collect()
+}
+
+// Strings that would read as JSON are quoted, so they stay strings
+fn to_cell(bson: &Bson) -> String {
+    match bson {
+        Bson::String(value) if serde_json::from_str::<serde_json::Value>(value).is_err() => value.clone(),
+        other => other.clone().into_relaxed_extjson().to_string()
+    }
+}
+
+fn from_cell(cell: &str) -> Result<Bson, String> {
+    match serde_json::from_str::<serde_json::Value>(cell) {
+        Ok(json) => Bson::try_from(json).map_err(|e| e.to_string()),
+        Err(_) => Ok(Bson::String(cell.to_string()))
+    }
+}
+
+fn write_csv(docs: &[Document], writer: impl Write) -> Result<(), String> {
+    let mut columns: Vec<&String> = Vec::new();
+    for key in docs.iter().flat_map(Document::keys) {
+        if !columns.contains(&key) {
+            columns.push(key);
+        }
+    }
+
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(&columns).map_err(|e| e.to_string())?;
+    for doc in docs {
+        csv.write_record(columns.iter().map(|column| doc.get(column.as_str()).map(to_cell).unwrap_or_default()))
+            .map_err(|e| e.to_string())?;
+    }
+    csv.flush().map_err(|e| e.to_string())
+}
+
+fn read_csv(reader: impl Read) -> Result<Vec<Document>, String> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let columns = csv.headers().map_err(|e| e.to_string())?.clone();
+    csv.records()
+        .map(|row| {
+            let row = row.map_err(|e| e.to_string())?;
+            let mut doc = Document::new();
+            for (column, cell) in columns.iter().zip(row.iter()) {
+                doc.insert(column, from_cell(cell)?);
+            }
+            Ok(doc)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use model::{Record, User, Warning};
+    use crate::memory::Memory;
+    use crate::recorder::{Filter, Recorder};
+    use crate::transfer::{date_field, export, import, Format};
+
+    fn users() -> Memory {
+        let memory = Memory::new();
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 1_i64, "first_name": "Alice", "username": "1234", "scam": true });
+        memory.insert(model::USERS_COLLECTION, doc! { "id": 2_i64, "first_name": "Bob, \"the\" builder", "scam": false });
+        memory
+    }
+
+    async fn round_trip(format: Format) {
+        let memory = users();
+        let mut file = Vec::new();
+        assert_eq!(export(&memory, "users", Filter::All, format, &mut file).await.unwrap(), 2);
+
+        let copy = Memory::new();
+        assert_eq!(import(&copy, "users", format, file.as_slice()).await.unwrap(), 2);
+        assert_eq!(import(&copy, "users", format, file.as_slice()).await.unwrap(), 2);
+
+        assert_eq!(copy.count::<User>(Filter::All).await.unwrap(), 2);
+        for id in 1..=2 {
+            let (original, copied) = (memory.get::<User>(id).await.unwrap().unwrap(), copy.get::<User>(id).await.unwrap().unwrap());
+            assert_eq!(original.to_doc(), copied.to_doc());
+        }
+    }
+
+    #[tokio::test]
+    async fn jsonl_round_trip() {
+        round_trip(Format::Jsonl).await;
+    }
+
+    #[tokio::test]
+    async fn csv_round_trip() {
+        round_trip(Format::Csv).await;
+    }
+
+    #[tokio::test]
+    async fn export_filters_by_flag() {
+        let memory = users();
+        let mut file = Vec::new();
+
+        assert_eq!(export(&memory, "users", Filter::eq("scam", true), Format::Jsonl, &mut file).await.unwrap(), 1);
+        assert!(String::from_utf8(file).unwrap().contains("Alice"));
+    }
+
+    #[tokio::test]
+    async fn unknown_collections_and_malformed_lines_are_errors() {
+        let memory = Memory::new();
+        memory.save(&Warning::new(1, -1, 5, 0)).await.unwrap();
+
+        assert!(export(&memory, "warnings", Filter::All, Format::Jsonl, Vec::new()).await.is_err());
+        assert!(import(&memory, "users", Format::Jsonl, "{\"id\": 1}\n".as_bytes()).await.is_err());
+        assert!(import(&memory, "users", Format::Jsonl, "not json\n".as_bytes()).await.is_err());
+        assert_eq!((date_field("rollups"), date_field("users")), (Some("day"), None));
+    }
+
+}
@@ -0,0 +1,225 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Expr, ExprLit, Field, Fields, Ident, Lit, Result, Token};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+
+/**
+*   Derives `model::Record` from the struct's fields.
+*
+*   On the struct :
+*   - `collection = EXPR` : name of the collection, required
+*   - `id = EXPR` : constant id, for records stored once like the config documents
+*   - `version = EXPR` : schema version of the documents, 1 by default
+*   - `upgrade = PATH` : `fn(&mut Document, from: i64)` upgrading older documents, before missing defaults are filled
+*
+*   On the fields :
+*   - `id` : the field is the record's id, defaults to the field named `id`
+*   - `chat_id` : the record is keyed by this chat id and its id
+*   - `rename = "name"` : name of the field in the document
+*   - `default` or `default = EXPR` : value used when the field is missing from the document, and written by upgrades
+*   - `decode = PATH` : function applied to the value read from the document
+*   - `encrypt` : the string is encrypted with `model::cipher` once a key is set, documents written in clear are sealed by upgrades and at startup
+*/
+#[proc_macro_derive(Record, attributes(record))]
+pub fn derive_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+enum Arg {
+    Flag(Ident),
+    Value(Ident, Box<Expr>)
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name: Ident = input.parse()?;
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Ok(Arg::Value(name, Box::new(input.parse()?)))
+        } else {
+            Ok(Arg::Flag(name))
+        }
+    }
+}
+
+fn args(attrs: &[Attribute]) -> Result<Vec<Arg>> {
+    let mut args = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("record")) {
+        args.extend(attr.parse_args_with(Punctuated::<Arg, Token![,]>::parse_terminated)?);
+    }
+    Ok(args)
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    id: bool,
+    chat_id: bool,
+    rename: Option<String>,
+    // Some(None) : `Default::default()`
+    default: Option<Option<Expr>>,
+    decode: Option<Expr>,
+    encrypt: bool
+}
+
+impl FieldOptions {
+
+    fn from(field: &Field) -> Result<Self> {
+        let mut options = FieldOptions::default();
+        for arg in args(&field.attrs)? {
+            match arg {
+                Arg::Flag(name) if name == "id" => options.id = true,
+                Arg::Flag(name) if name == "chat_id" => options.chat_id = true,
+                Arg::Flag(name) if name == "default" => options.default = Some(None),
+                Arg::Flag(name) if name == "encrypt" => options.encrypt = true,
+                Arg::Value(name, value) if name == "default" => options.default = Some(Some(*value)),
+                Arg::Value(name, value) if name == "decode" => options.decode = Some(*value),
+                Arg::Value(name, value) if name == "rename" => match *value {
+                    Expr::Lit(ExprLit { lit: Lit::Str(rename), .. }) => options.rename = Some(rename.value()),
+                    other => return Err(Error::new_spanned(other, "expected a string literal"))
+                },
+                Arg::Flag(name) | Arg::Value(name, _) => return Err(Error::new_spanned(&name, format!("unknown record attribute `{}`", name)))
+            }
+        }
+        Ok(options)
+    }
+
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(name, "Record can only be derived for structs with named fields"))
+        },
+        _ => return Err(Error::new_spanned(name, "Record can only be derived for structs"))
+    };
+
+    let (mut collection, mut constant_id, mut version, mut upgrade) = (None, None, None, None);
+    for arg in args(&input.attrs)? {
+        match arg {
+            Arg::Value(arg, value) if arg == "collection" => collection = Some(*value),
+            Arg::Value(arg, value) if arg == "id" => constant_id = Some(*value),
+            Arg::Value(arg, value) if arg == "version" => version = Some(*value),
+            Arg::Value(arg, value) if arg == "upgrade" => upgrade = Some(*value),
+            Arg::Flag(arg) | Arg::Value(arg, _) => return Err(Error::new_spanned(&arg, format!("unknown record attribute `{}`", arg)))
+        }
+    }
+    let collection = collection.ok_or_else(|| Error::new_spanned(name, "missing #[record(collection = ...)]"))?;
+
+    let (mut decoded, mut encoded, mut defaults, mut sealed, mut encrypted) = (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (mut id, mut chat_id) = (None, None);
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let options = FieldOptions::from(field)?;
+        let key = options.rename.clone().unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+
+        if options.id || (ident == "id" && id.is_none()) {
+            id = Some(ident);
+        }
+        if options.chat_id {
+            chat_id = Some(ident);
+        }
+
+        let value = match &options.default {
+            None => quote! { ::model::require::<#ty>(doc, Self::collection(), #key)? },
+            Some(None) => quote! { ::model::decode::<#ty>(doc, Self::collection(), #key)?.unwrap_or_default() },
+            Some(Some(default)) => quote! { ::model::decode::<#ty>(doc, Self::collection(), #key)?.unwrap_or_else(|| #default) }
+        };
+        if let Some(default) = &options.default {
+            let default = match default {
+                None => quote! { <#ty as Default>::default() },
+                Some(default) => quote! { #default }
+            };
+            defaults.push(quote! {
+                if !doc.contains_key(#key) {
+                    let value: #ty = #default;
+                    doc.insert(#key, ::model::Field::to_bson(&value));
+                }
+            });
+        }
+        let value = match options.encrypt {
+            true => quote! { ::model::cipher::decrypt(Self::collection(), #key, #value)? },
+            false => value
+        };
+        let value = match &options.decode {
+            Some(decode) => quote! { #decode(#value) },
+            None => value
+        };
+        decoded.push(quote! { #ident: #value });
+        if options.encrypt {
+            encoded.push(quote! { doc.insert(#key, ::model::cipher::encrypt(&self.#ident)); });
+            sealed.push(quote! { ::model::cipher::seal(doc, #key); });
+            encrypted.push(key.clone());
+        } else {
+            encoded.push(quote! { doc.insert(#key, ::model::Field::to_bson(&self.#ident)); });
+        }
+    }
+    encoded.push(quote! { doc.insert(::model::SCHEMA_VERSION, Self::SCHEMA_VERSION); });
+
+    let id = match (constant_id, id) {
+        (Some(constant), _) => {
+            encoded.insert(0, quote! { doc.insert("id", #constant); });
+            quote! { #constant }
+        },
+        (None, Some(id)) => quote! { self.#id },
+        (None, None) => return Err(Error::new_spanned(name, "missing an `id` field, #[record(id)] field or #[record(id = ...)]"))
+    };
+    let version = version.map(|version| quote! {
+        const SCHEMA_VERSION: i64 = #version;
+    });
+    let encrypted = (!encrypted.is_empty()).then(|| quote! {
+        const ENCRYPTED: &'static [&'static str] = &[#(#encrypted),*];
+    });
+    let upgrade = upgrade.map(|upgrade| quote! { #upgrade(doc, from); });
+    let key = chat_id.map(|chat_id| quote! {
+        fn key(&self) -> ::model::Key {
+            ::model::Key::Chat(self.#chat_id, self.id())
+        }
+    });
+
+    Ok(quote! {
+        impl ::model::Record for #name {
+
+            #version
+
+            #encrypted
+
+            fn from_doc(doc: &::mongodb::bson::Document) -> Result<Self, ::model::DecodeError> {
+                Ok(#name {
+                    #(#decoded),*
+                })
+            }
+
+            fn to_doc(&self) -> ::mongodb::bson::Document {
+                let mut doc = ::mongodb::bson::Document::new();
+                #(#encoded)*
+                doc
+            }
+
+            fn collection() -> &'static str {
+                #collection
+            }
+
+            fn id(&self) -> i64 {
+                #id
+            }
+
+            #key
+
+            #[allow(unused_variables)]
+            fn upgrade(doc: &mut ::mongodb::bson::Document, from: i64) {
+                #upgrade
+                #(#defaults)*
+                #(#sealed)*
+            }
+
+        }
+    })
+}
@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use mongodb::bson::{Bson, Document, doc};
+use crate::{decode, require, DecodeError, Record, SCHEMA_VERSION};
+
+pub static COLLECTION: &str = "audit";
+
+pub static ID: &str = "id";
+pub static DATE: &str = "date";
+pub static AUTHOR: &str = "author";
+pub static TARGET_COLLECTION: &str = "collection";
+pub static TARGET: &str = "target";
+pub static FIELD: &str = "field";
+pub static OLD: &str = "old";
+pub static NEW: &str = "new";
+pub static REASON: &str = "reason";
+pub static REVERTS: &str = "reverts";
+
+// Field of the changes replacing a whole document
+pub static WHOLE_DOCUMENT: &str = "";
+
+// Last id given, so the changes made in the same nanosecond keep their order
+static LAST_ID: AtomicI64 = AtomicI64::new(0);
+
+/**
+*   An administrative change of a stored field or document : who made it, when, why, and the values before and after.
+*   A missing value is `Null`, e.g. the old keywords of a first sync.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audit {
+    // Nanoseconds since the epoch, unique and increasing
+    id: i64,
+    date: i64,
+    author: String,
+    collection: String,
+    target: i64,
+    field: String,
+    old: Bson,
+    new: Bson,
+    reason: String,
+    // Id of the change this one reverts
+    reverts: Option<i64>
+}
+
+impl Audit {
+
+    pub fn new(author: &str, reason: &str, collection: &str, target: i64, field: &str, old: Bson, new: Bson) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let nanos = now.as_nanos() as i64;
+        let last = LAST_ID.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(nanos.max(last + 1))).unwrap();
+        Audit {
+            id: nanos.max(last + 1),
+            date: now.as_secs() as i64,
+            author: author.to_string(),
+            collection: collection.to_string(),
+            target,
+            field: field.to_string(),
+            old,
+            new,
+            reason: reason.to_string(),
+            reverts: None
+        }
+    }
+
+    /**
+    *   The change restoring the old value of this one
+    */
+    pub fn revert(&self, author: &str, reason: &str) -> Self {
+        Audit {
+            reverts: Some(self.id),
+            ..Audit::new(author, reason, &self.collection, self.target, &self.field, self.new.clone(), self.old.clone())
+        }
+    }
+
+    pub fn date(&self) -> i64 {
+        self.date
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn target_collection(&self) -> &str {
+        &self.collection
+    }
+
+    pub fn target(&self) -> i64 {
+        self.target
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn old(&self) -> &Bson {
+        &self.old
+    }
+
+    pub fn new_value(&self) -> &Bson {
+        &self.new
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn reverts(&self) -> Option<i64> {
+        self.reverts
+    }
+
+}
+
+impl Record for Audit {
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        Ok(Audit {
+            id: require(doc, COLLECTION, ID)?,
+            date: require(doc, COLLECTION, DATE)?,
+            author: require(doc, COLLECTION, AUTHOR)?,
+            collection: require(doc, COLLECTION, TARGET_COLLECTION)?,
+            target: require(doc, COLLECTION, TARGET)?,
+            field: decode(doc, COLLECTION, FIELD)?.unwrap_or_default(),
+            old: doc.get(OLD).cloned().unwrap_or(Bson::Null),
+            new: doc.get(NEW).cloned().unwrap_or(Bson::Null),
+            reason: decode(doc, COLLECTION, REASON)?.unwrap_or_default(),
+            reverts: decode(doc, COLLECTION, REVERTS)?
+        })
+    }
+
+    fn to_doc(&self) -> Document {
+        let mut doc = doc! {
+            ID: self.id,
+            DATE: self.date,
+            AUTHOR: self.author.clone(),
+            TARGET_COLLECTION: self.collection.clone(),
+            TARGET: self.target,
+            FIELD: self.field.clone(),
+            OLD: self.old.clone(),
+            NEW: self.new.clone(),
+            REASON: self.reason.clone()
+        };
+        if let Some(reverts) = self.reverts {
+            doc.insert(REVERTS, reverts);
+        }
+        doc.insert(SCHEMA_VERSION, Self::SCHEMA_VERSION);
+        doc
+    }
+
+    fn collection() -> &'static str {
+        COLLECTION
+    }
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{Bson, doc};
+    use crate::{Audit, Record};
+
+    #[test]
+    fn reverts_swap_the_values() {
+        let change = Audit::new("alice", "Reported twice", "users", 42, "scam", Bson::Boolean(false), Bson::Boolean(true));
+        let revert = change.revert("bob", "Wrong user");
+
+        assert!(revert.id() > change.id());
+        assert_eq!(revert.reverts(), Some(change.id()));
+        assert_eq!((revert.old(), revert.new_value()), (change.new_value(), change.old()));
+        assert_eq!(Audit::from_doc(&revert.to_doc()).unwrap(), revert);
+
+        let first_sync = Audit::new("alice", "First list", "config", 0, "", Bson::Null, Bson::Document(doc! { "fr": ["DISPO"] }));
+        assert_eq!(Audit::from_doc(&first_sync.to_doc()).unwrap(), first_sync);
+    }
+
+}
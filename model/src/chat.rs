@@ -1,76 +1,165 @@
-use mongodb::bson::{Bson, Document, doc};
-use rtdlib::types::ChatType;
-use crate::Record;
+use rtdlib::types::{ChatMemberStatus, ChatType};
+use crate::{Merge, Record};
 
 pub static COLLECTION: &str = "chats";
 
 pub static ID: &str = "id";
 pub static TITLE: &str = "title";
 pub static TYPE: &str = "type";
+pub static WRITE_FORBIDDEN: &str = "write_forbidden";
+pub static MEMBER_COUNT: &str = "member_count";
+pub static STATUS: &str = "status";
+pub static CAN_DELETE_MESSAGES: &str = "can_delete_messages";
+pub static CAN_RESTRICT_MEMBERS: &str = "can_restrict_members";
+pub static CAN_SEND_MESSAGES: &str = "can_send_messages";
+pub static DESCRIPTION: &str = "description";
+pub static LAST_ACTIVITY: &str = "last_activity";
+pub static SCAM_COUNT: &str = "scam_count";
+pub static WARNING_COUNT: &str = "warning_count";
 
+/**
+*   A chat we're in, kept up to date by `ChatUpdate`s
+*/
+#[derive(Record)]
+#[record(collection = COLLECTION)]
 pub struct Chat {
     id: i64,
     title: String,
-    type_: String
+    #[record(rename = "type")]
+    type_: String,
+    #[record(default)]
+    write_forbidden: bool,
+    #[record(default)]
+    member_count: i64,
+    // Our own status in the chat, see `status_name`
+    #[record(default)]
+    status: String,
+    // Our admin rights
+    #[record(default)]
+    can_delete_messages: bool,
+    #[record(default)]
+    can_restrict_members: bool,
+    // Members' permission
+    #[record(default = true)]
+    can_send_messages: bool,
+    #[record(default)]
+    description: String,
+    // Date of the last message
+    #[record(default)]
+    last_activity: i64,
+    #[record(default)]
+    scam_count: i64,
+    #[record(default)]
+    warning_count: i64
 }
 
 impl Chat {
 
     pub fn from_td(chat: &rtdlib::types::Chat) -> Self {
         Chat {
-            id: chat.id(),
             title: chat.title().to_string(),
-            type_: match chat.type_() {
-                ChatType::BasicGroup(_) => "BasicGroup",
-                ChatType::Private(_) => "Private",
-                ChatType::Secret(_) => "Secret",
-                ChatType::Supergroup(_) => "Supergroup",
-                _ => "Unknown"
-            }.to_string()
+            type_: type_name(chat.type_()).to_string(),
+            can_send_messages: chat.permissions().can_send_messages(),
+            ..Chat::unknown(chat.id())
         }
     }
 
-}
-
-impl Record for Chat {
-
-    fn from_doc(doc: &Document) -> Self {
+    // Chat we only know the id of
+    pub(crate) fn unknown(id: i64) -> Self {
         Chat {
-            id: doc.get(ID).and_then(Bson::as_i64).unwrap(),
-            title: doc.get(TITLE).and_then(Bson::as_str).unwrap().to_string(),
-            type_: doc.get(TYPE).and_then(Bson::as_str).unwrap().to_string()
+            id,
+            title: String::new(),
+            type_: String::from("Unknown"),
+            write_forbidden: false,
+            member_count: 0,
+            status: String::new(),
+            can_delete_messages: false,
+            can_restrict_members: false,
+            can_send_messages: true,
+            description: String::new(),
+            last_activity: 0,
+            scam_count: 0,
+            warning_count: 0
         }
     }
 
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: self.id,
-            TITLE: self.title.to_string(),
-            TYPE: self.type_.to_string()
-        };
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn member_count(&self) -> i64 {
+        self.member_count
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
     }
 
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: self.id,
-                TITLE: self.title.to_string(),
-                TYPE: self.type_.to_string()
-            }
-        };
+    pub fn description(&self) -> &str {
+        &self.description
     }
 
-    fn collection_name(&self) -> &str {
-        COLLECTION
+    pub fn last_activity(&self) -> i64 {
+        self.last_activity
     }
 
-    fn id(&self) -> i64 {
-        self.id
+    pub fn scam_count(&self) -> i64 {
+        self.scam_count
+    }
+
+    pub fn warning_count(&self) -> i64 {
+        self.warning_count
+    }
+
+    pub fn is_write_forbidden(&self) -> bool {
+        self.write_forbidden
+    }
+
+    pub fn forbid_write(&mut self) -> &mut Self {
+        self.write_forbidden = true;
+        self
     }
 
+    // We're in the chat and can at least warn its members
+    pub fn is_protected(&self) -> bool {
+        let member = matches!(self.status.as_str(), "Creator" | "Administrator" | "Member");
+        let can_write = !self.write_forbidden && (self.can_send_messages || self.status != "Member");
+        member && can_write
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.can_delete_messages || self.can_restrict_members
+    }
+
+}
+
+impl Merge for Chat {
+
     fn merge(&mut self, new: &Self) -> &mut Self {
         self.title = new.title.clone();
         self
     }
 
 }
+
+pub(crate) fn type_name(type_: &ChatType) -> &'static str {
+    match type_ {
+        ChatType::BasicGroup(_) => "BasicGroup",
+        ChatType::Private(_) => "Private",
+        ChatType::Secret(_) => "Secret",
+        ChatType::Supergroup(_) => "Supergroup",
+        _ => "Unknown"
+    }
+}
+
+pub fn status_name(status: &ChatMemberStatus) -> &'static str {
+    match status {
+        ChatMemberStatus::Creator(_) => "Creator",
+        ChatMemberStatus::Administrator(_) => "Administrator",
+        ChatMemberStatus::Member(_) => "Member",
+        ChatMemberStatus::Restricted(_) => "Restricted",
+        ChatMemberStatus::Left(_) => "Left",
+        ChatMemberStatus::Banned(_) => "Banned",
+        _ => ""
+    }
+}
@@ -0,0 +1,149 @@
+use mongodb::bson::{Bson, Document, doc};
+use rtdlib::types::{ChatMemberStatus, ChatPermissions};
+use crate::{require, DecodeError, Record, SCHEMA_VERSION};
+use crate::chat::{self, Chat, COLLECTION};
+
+/**
+*   Fields of a chat refreshed by an update, written without reading the chat first.
+*   Counters are incremented by the backend, so concurrent updates don't lose any.
+*   A chat we didn't know yet is created with the defaults of `Chat`.
+*/
+#[derive(Debug)]
+pub struct ChatUpdate {
+    id: i64,
+    set: Document,
+    inc: Document
+}
+
+impl ChatUpdate {
+
+    fn new(id: i64, set: Document) -> Self {
+        ChatUpdate {
+            id,
+            set,
+            inc: Document::new()
+        }
+    }
+
+    pub fn from_td(chat: &rtdlib::types::Chat) -> Self {
+        ChatUpdate::new(chat.id(), doc! {
+            chat::TITLE: chat.title().to_string(),
+            chat::TYPE: chat::type_name(chat.type_()),
+            chat::CAN_SEND_MESSAGES: chat.permissions().can_send_messages()
+        })
+    }
+
+    pub fn title(id: i64, title: &str) -> Self {
+        ChatUpdate::new(id, doc! { chat::TITLE: title.to_string() })
+    }
+
+    pub fn permissions(id: i64, permissions: &ChatPermissions) -> Self {
+        ChatUpdate::new(id, doc! { chat::CAN_SEND_MESSAGES: permissions.can_send_messages() })
+    }
+
+    // Our status and rights, the member count is left as is when unknown
+    pub fn membership(id: i64, status: &ChatMemberStatus, member_count: i64) -> Self {
+        let (can_delete_messages, can_restrict_members) = match status {
+            ChatMemberStatus::Creator(_) => (true, true),
+            ChatMemberStatus::Administrator(admin) => (admin.can_delete_messages(), admin.can_restrict_members()),
+            _ => (false, false)
+        };
+        let mut set = doc! {
+            chat::STATUS: chat::status_name(status),
+            chat::CAN_DELETE_MESSAGES: can_delete_messages,
+            chat::CAN_RESTRICT_MEMBERS: can_restrict_members
+        };
+        if member_count > 0 {
+            set.insert(chat::MEMBER_COUNT, member_count);
+        }
+        ChatUpdate::new(id, set)
+    }
+
+    pub fn full_info(id: i64, description: &str, member_count: i64) -> Self {
+        let mut set = doc! { chat::DESCRIPTION: description.to_string() };
+        if member_count > 0 {
+            set.insert(chat::MEMBER_COUNT, member_count);
+        }
+        ChatUpdate::new(id, set)
+    }
+
+    pub fn activity(id: i64, date: i64) -> Self {
+        ChatUpdate::new(id, doc! { chat::LAST_ACTIVITY: date })
+    }
+
+    pub fn scam(id: i64) -> Self {
+        ChatUpdate {
+            inc: doc! { chat::SCAM_COUNT: 1_i64 },
+            ..ChatUpdate::new(id, Document::new())
+        }
+    }
+
+    pub fn warning(id: i64) -> Self {
+        ChatUpdate {
+            inc: doc! { chat::WARNING_COUNT: 1_i64 },
+            ..ChatUpdate::new(id, Document::new())
+        }
+    }
+
+    pub fn forbid_write(id: i64) -> Self {
+        ChatUpdate::new(id, doc! { chat::WRITE_FORBIDDEN: true })
+    }
+
+}
+
+impl Record for ChatUpdate {
+
+    // Only the fields set are read back
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        let set = doc.iter()
+            .filter(|(key, _)| key.as_str() != chat::ID && key.as_str() != SCHEMA_VERSION)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(ChatUpdate::new(require(doc, COLLECTION, chat::ID)?, set))
+    }
+
+    fn to_doc(&self) -> Document {
+        let mut doc = Chat::unknown(self.id).to_doc();
+        for (key, value) in self.set.iter().chain(self.inc.iter()) {
+            doc.insert(key.clone(), value.clone());
+        }
+        doc
+    }
+
+    fn to_doc_update(&self) -> Document {
+        let mut update = Document::new();
+        if !self.set.is_empty() {
+            update.insert("$set", Bson::Document(self.set.clone()));
+        }
+        if !self.inc.is_empty() {
+            update.insert("$inc", Bson::Document(self.inc.clone()));
+        }
+        update
+    }
+
+    fn collection() -> &'static str {
+        COLLECTION
+    }
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Chat, ChatUpdate, Record};
+
+    #[test]
+    fn unknown_chats_are_created_with_defaults() {
+        let chat = Chat::from_doc(&ChatUpdate::scam(-1).to_doc()).unwrap();
+
+        assert_eq!(chat.scam_count(), 1);
+        assert_eq!(chat.warning_count(), 0);
+        assert!(!chat.is_protected());
+        assert_eq!(ChatUpdate::scam(-1).to_doc_update().get_document("$inc").unwrap().get_i64("scam_count").unwrap(), 1);
+        assert!(!ChatUpdate::scam(-1).to_doc_update().contains_key("$set"));
+    }
+
+}
@@ -0,0 +1,165 @@
+use std::fmt::{Display, Formatter};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use mongodb::bson::{Bson, Document};
+use once_cell::sync::OnceCell;
+use crate::DecodeError;
+
+// Environment variable holding the base64 encoded 32 bytes key
+pub static KEY_VAR: &str = "FETISH_ENCRYPTION_KEY";
+
+// Marks the encrypted values, followed by the base64 of the nonce and the ciphertext
+static PREFIX: &str = "enc:";
+static NONCE_SIZE: usize = 12;
+
+static CIPHER: OnceCell<Aes256Gcm> = OnceCell::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyError {
+    NotBase64,
+    WrongSize(usize),
+    AlreadySet
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyError::NotBase64 => write!(f, "{} isn't base64", KEY_VAR),
+            KeyError::WrongSize(size) => write!(f, "{} is {} bytes long instead of 32", KEY_VAR, size),
+            KeyError::AlreadySet => write!(f, "The encryption key is already set")
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/**
+*   Sets the key encrypting the `#[record(encrypt)]` fields, they're written in clear until it is set
+*/
+pub fn init(key: &str) -> Result<(), KeyError> {
+    let key = base64::decode(key.trim()).map_err(|_| KeyError::NotBase64)?;
+    if key.len() != 32 {
+        return Err(KeyError::WrongSize(key.len()));
+    }
+    CIPHER.set(Aes256Gcm::new_from_slice(&key).unwrap()).map_err(|_| KeyError::AlreadySet)
+}
+
+pub fn is_set() -> bool {
+    CIPHER.get().is_some()
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/**
+*   Encrypts the value with a fresh nonce, empty values and values written without a key stay in clear
+*/
+pub fn encrypt(value: &str) -> String {
+    match CIPHER.get() {
+        Some(cipher) if !value.is_empty() => {
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let mut sealed = nonce.to_vec();
+            sealed.extend(cipher.encrypt(&nonce, value.as_bytes()).unwrap());
+            format!("{}{}", PREFIX, base64::encode(sealed))
+        },
+        _ => value.to_string()
+    }
+}
+
+/**
+*   Decrypts a value read from a document, values stored in clear are returned as they are
+*/
+pub fn decrypt(collection: &'static str, field: &str, value: String) -> Result<String, DecodeError> {
+    if !is_encrypted(&value) {
+        return Ok(value);
+    }
+    let error = || DecodeError::Encrypted { collection, field: field.to_string() };
+    let cipher = CIPHER.get().ok_or_else(error)?;
+    let sealed = base64::decode(&value[PREFIX.len()..]).map_err(|_| error())?;
+    if sealed.len() < NONCE_SIZE {
+        return Err(error());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+    let clear = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| error())?;
+    String::from_utf8(clear).map_err(|_| error())
+}
+
+/**
+*   Encrypts a field stored in clear, for the upgrades of documents written before the key was set
+*/
+pub fn seal(doc: &mut Document, field: &str) {
+    if let Some(Bson::String(value)) = doc.get(field) {
+        if !is_encrypted(value) {
+            let sealed = encrypt(value);
+            doc.insert(field, sealed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use crate::{cipher, DecodeError, Record, User};
+
+    fn key() {
+        // Tests share the key, only the first one sets it
+        let _ = cipher::init("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+    }
+
+    #[test]
+    fn encrypted_values_round_trip() {
+        key();
+        let sealed = cipher::encrypt("+33612345678");
+
+        assert!(cipher::is_encrypted(&sealed));
+        assert!(!sealed.contains("33612345678"));
+        assert_ne!(sealed, cipher::encrypt("+33612345678"));
+        assert_eq!(cipher::decrypt("users", "phone_number", sealed), Ok(String::from("+33612345678")));
+        assert_eq!(cipher::decrypt("users", "phone_number", String::from("+33612345678")), Ok(String::from("+33612345678")));
+        assert_eq!(cipher::encrypt(""), "");
+    }
+
+    #[test]
+    fn tampered_values_are_errors() {
+        key();
+        let mut sealed = cipher::encrypt("Bonjour");
+        sealed.pop();
+        sealed.push('A');
+
+        assert_eq!(cipher::decrypt("messages", "content", sealed), Err(DecodeError::Encrypted { collection: "messages", field: String::from("content") }));
+    }
+
+    #[test]
+    fn seal_encrypts_clear_fields_once() {
+        key();
+        let mut doc = doc! { "phone_number": "+33612345678" };
+        cipher::seal(&mut doc, "phone_number");
+        let sealed = doc.get_str("phone_number").unwrap().to_string();
+        cipher::seal(&mut doc, "phone_number");
+
+        assert!(cipher::is_encrypted(&sealed));
+        assert_eq!(doc.get_str("phone_number").unwrap(), sealed);
+    }
+
+    #[test]
+    fn encrypted_record_fields() {
+        key();
+        let mut doc = doc! { "id": 1_i64, "first_name": "Alice", "phone_number": "+33612345678" };
+        let user = User::from_doc(&doc).unwrap();
+        User::upgrade(&mut doc, 1);
+
+        assert!(cipher::is_encrypted(user.to_doc().get_str("phone_number").unwrap()));
+        assert!(cipher::is_encrypted(doc.get_str("phone_number").unwrap()));
+        assert_eq!(cipher::decrypt("users", "phone_number", doc.get_str("phone_number").unwrap().to_string()), Ok(String::from("+33612345678")));
+        assert!(User::from_doc(&doc).is_ok());
+        assert_eq!(User::ENCRYPTED, &["phone_number"]);
+    }
+
+    #[test]
+    fn wrong_keys_are_refused() {
+        assert_eq!(cipher::init("not a key"), Err(cipher::KeyError::NotBase64));
+        assert_eq!(cipher::init("c2hvcnQ="), Err(cipher::KeyError::WrongSize(5)));
+    }
+
+}
@@ -1,22 +1,23 @@
-use mongodb::bson::{Bson, Document, doc};
 use crate::Record;
 
 pub static COLLECTION: &str = "config";
 pub static KEYWORDS_ID: i64 = 0;
 pub static FORBIDDEN_NAMES_ID: i64 = 1;
 
-pub static ID: &str = "id";
-pub static FR: &str = "fr";
-pub static EN: &str = "en";
-pub static DE: &str = "de";
-pub static NAMES: &str = "names";
-
+#[derive(Record)]
+#[record(collection = COLLECTION, id = KEYWORDS_ID)]
 pub struct Keywords {
+    #[record(decode = uppercase)]
     fr: Vec<String>,
+    #[record(decode = uppercase)]
     en: Vec<String>,
+    #[record(decode = uppercase)]
     de: Vec<String>
 }
+#[derive(Record)]
+#[record(collection = COLLECTION, id = FORBIDDEN_NAMES_ID)]
 pub struct ForbiddenNames {
+    #[record(decode = uppercase)]
     names: Vec<String>
 }
 
@@ -29,6 +30,26 @@ pub enum KeywordMatch {
 
 impl Keywords {
 
+    pub fn new(fr: Vec<String>, en: Vec<String>, de: Vec<String>) -> Self {
+        Keywords {
+            fr: uppercase(fr),
+            en: uppercase(en),
+            de: uppercase(de)
+        }
+    }
+
+    // The lists by language code
+    pub fn languages(&self) -> [(&'static str, &Vec<String>); 3] {
+        [("fr", &self.fr), ("en", &self.en), ("de", &self.de)]
+    }
+
+    // The language and keyword found in the text, for the evidence of a sanction
+    pub fn find(&self, text: &str) -> Option<(&'static str, &String)> {
+        let text = text.to_uppercase();
+        self.languages().iter()
+            .find_map(|(language, keywords)| keywords.iter().find(|key| text.contains(key.as_str())).map(|key| (*language, key)))
+    }
+
     pub fn text_match(&self, text: &str) -> KeywordMatch {
         let ok = |keywords: &Vec<String>| keywords.iter().any(|key| text.to_uppercase().contains(key));
 
@@ -45,92 +66,25 @@ impl Keywords {
 
 }
 
-impl Record for Keywords {
+impl ForbiddenNames {
 
-    fn from_doc(doc: &Document) -> Self {
-        Keywords {
-            fr: doc.get(FR).and_then(Bson::as_array).unwrap().iter().map(|bson| bson.as_str().unwrap().to_string().to_uppercase()).collect(),
-            en: doc.get(EN).and_then(Bson::as_array).unwrap().iter().map(|bson| bson.as_str().unwrap().to_string().to_uppercase()).collect(),
-            de: doc.get(DE).and_then(Bson::as_array).unwrap().iter().map(|bson| bson.as_str().unwrap().to_string().to_uppercase()).collect()
+    pub fn new(names: Vec<String>) -> Self {
+        ForbiddenNames {
+            names: uppercase(names)
         }
     }
 
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: KEYWORDS_ID,
-            FR: self.fr.clone(),
-            EN: self.en.clone(),
-            DE: self.de.clone()
-        };
-    }
-
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: KEYWORDS_ID,
-                FR: self.fr.clone(),
-                EN: self.en.clone(),
-                DE: self.de.clone()
-            }
-        };
-    }
-
-    fn collection_name(&self) -> &str {
-        COLLECTION
-    }
-
-    fn id(&self) -> i64 {
-        KEYWORDS_ID
-    }
-
-    fn merge(&mut self, _new: &Self) -> &mut Self {
-        todo!()
+    pub fn names(&self) -> &Vec<String> {
+        &self.names
     }
 
-}
-
-impl ForbiddenNames {
-
     pub fn name_match(&self, first_name: &str, last_name: &str) -> bool {
         self.names.iter().any(|name| first_name.to_uppercase().contains(name) || last_name.to_uppercase().contains(name))
     }
 
 }
 
-impl Record for ForbiddenNames {
-
-    fn from_doc(doc: &Document) -> Self {
-        ForbiddenNames {
-            names: doc.get(NAMES).and_then(Bson::as_array).unwrap().iter().map(|bson| bson.as_str().unwrap().to_string().to_uppercase()).collect()
-        }
-    }
-
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: FORBIDDEN_NAMES_ID,
-            NAMES: self.names.clone()
-        };
-    }
-
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: FORBIDDEN_NAMES_ID,
-                NAMES: self.names.clone()
-            }
-        };
-    }
-
-    fn collection_name(&self) -> &str {
-        COLLECTION
-    }
-
-    fn id(&self) -> i64 {
-        FORBIDDEN_NAMES_ID
-    }
-
-    fn merge(&mut self, _new: &Self) -> &mut Self {
-        todo!()
-    }
-
+// Matching is done on uppercase text, whatever case the lists were written in
+fn uppercase(words: Vec<String>) -> Vec<String> {
+    words.iter().map(|word| word.to_uppercase()).collect()
 }
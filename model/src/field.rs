@@ -0,0 +1,114 @@
+use mongodb::bson::{Bson, Document};
+use crate::DecodeError;
+
+/**
+*   Conversion of a record's field from and to BSON, used by `#[derive(Record)]`
+*/
+pub trait Field: Sized {
+
+    // Expected BSON type, for decoding errors
+    const TYPE: &'static str;
+
+    fn from_bson(bson: &Bson) -> Option<Self>;
+    fn to_bson(&self) -> Bson;
+
+}
+
+impl Field for i64 {
+
+    const TYPE: &'static str = "integer";
+
+    // Documents written by hand in the Mongo shell may hold 32 bits integers
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        match bson {
+            Bson::Int64(i) => Some(*i),
+            Bson::Int32(i) => Some(*i as i64),
+            _ => None
+        }
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::Int64(*self)
+    }
+
+}
+
+impl Field for bool {
+
+    const TYPE: &'static str = "boolean";
+
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        bson.as_bool()
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::Boolean(*self)
+    }
+
+}
+
+impl Field for String {
+
+    const TYPE: &'static str = "string";
+
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        bson.as_str().map(String::from)
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::String(self.clone())
+    }
+
+}
+
+impl Field for Document {
+
+    const TYPE: &'static str = "document";
+
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        bson.as_document().cloned()
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::Document(self.clone())
+    }
+
+}
+
+impl<T: Field> Field for Vec<T> {
+
+    const TYPE: &'static str = "array";
+
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        bson.as_array()?.iter().map(T::from_bson).collect()
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::Array(self.iter().map(Field::to_bson).collect())
+    }
+
+}
+
+/**
+*   Reads a field of a document, `None` when it's missing
+*/
+pub fn decode<T: Field>(doc: &Document, collection: &'static str, field: &str) -> Result<Option<T>, DecodeError> {
+    match doc.get(field) {
+        None | Some(Bson::Null) => Ok(None),
+        Some(bson) => T::from_bson(bson).map(Some).ok_or_else(|| DecodeError::WrongType {
+            collection,
+            field: field.to_string(),
+            expected: T::TYPE
+        })
+    }
+}
+
+/**
+*   Reads a field that every document must have
+*/
+pub fn require<T: Field>(doc: &Document, collection: &'static str, field: &str) -> Result<T, DecodeError> {
+    decode(doc, collection, field)?.ok_or_else(|| DecodeError::Missing {
+        collection,
+        field: field.to_string()
+    })
+}
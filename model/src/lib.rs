@@ -1,24 +1,45 @@
+extern crate self as model;
+
 mod user;
 mod message;
 mod chat;
+mod chat_update;
 mod config;
 mod stats;
 mod record;
+mod field;
 mod sanction;
+mod report;
+mod warning;
+mod rollup;
+mod audit;
+pub mod cipher;
 
-pub use user::User;
+pub use user::{User, NameChange};
 pub use message::Message;
-pub use chat::Chat;
+pub use chat::{Chat, status_name};
+pub use chat_update::ChatUpdate;
 pub use config::{Keywords, KeywordMatch, ForbiddenNames};
 pub use stats::MessageSent;
-pub use record::Record;
+pub use record::{Key, Record, Merge, DecodeError, SCHEMA_VERSION};
+pub use field::{Field, decode, require};
+pub use model_derive::Record;
 pub use sanction::{ScamType, Sanction};
+pub use report::{Report, KIND_SUPERGROUP_SPAM, KIND_CHAT};
+pub use warning::Warning;
+pub use audit::{Audit, WHOLE_DOCUMENT};
+pub use rollup::{Counts, Dimension, MessageSeen, Rollup, day_of, DAY_SECONDS};
 
 pub use user::COLLECTION as USERS_COLLECTION;
 pub use message::COLLECTION as MESSAGES_COLLECTION;
 pub use chat::COLLECTION as CHATS_COLLECTION;
 pub use config::COLLECTION as CONFIG_COLLECTION;
 pub use sanction::COLLECTION as STATS_COLLECTION;
+pub use sanction::CHAT_UNKNOWN;
+pub use report::COLLECTION as REPORTS_COLLECTION;
+pub use warning::COLLECTION as WARNINGS_COLLECTION;
+pub use rollup::COLLECTION as ROLLUPS_COLLECTION;
+pub use audit::COLLECTION as AUDIT_COLLECTION;
 
 pub use config::KEYWORDS_ID;
 pub use config::FORBIDDEN_NAMES_ID;
@@ -1,30 +1,25 @@
-use mongodb::bson::{Bson, Document, doc};
 use rtdlib::types::{MessageContent, MessageSenderUser};
-use crate::Record;
+use crate::{Merge, Record};
 
 pub static COLLECTION: &str = "messages";
 
-pub static ID: &str = "id";
-pub static SENDER: &str = "sender";
-pub static CHAT_ID: &str = "chat_id";
-pub static DATE: &str = "date";
-pub static EDIT_DATE: &str = "edit_date";
-pub static RESTRICTION_REASON: &str = "restriction_reason";
-pub static TYPE: &str = "type";
-pub static CONTENT: &str = "content";
-pub static EXTRA: &str = "extra";
-pub static IS_SCAM: &str = "is_scam";
-pub static TRIGGER: &str = "trigger";
-
+#[derive(Record)]
+// Version 2 : encrypted content
+#[record(collection = COLLECTION, version = 2)]
 pub struct Message {
     id: i64,
     sender: i64,
+    #[record(chat_id)]
     chat_id: i64,
     date: i64,
     edit_date: i64,
     restriction_reason: String,
+    #[record(rename = "type")]
     type_: String,
+    // Removed by the retention rules, the message is kept for the counters
+    #[record(default, encrypt)]
     content: String,
+    #[record(default)]
     extra: Vec<String>,
     is_scam: bool,
     trigger: bool
@@ -97,67 +92,21 @@ impl Message {
         }
     }
 
-}
-
-impl Record for Message {
-
-    fn from_doc(doc: &Document) -> Self {
-        Message {
-            id: doc.get(ID).and_then(Bson::as_i64).unwrap(),
-            sender: doc.get(SENDER).and_then(Bson::as_i64).unwrap(),
-            chat_id: doc.get(CHAT_ID).and_then(Bson::as_i64).unwrap(),
-            date: doc.get(DATE).and_then(Bson::as_i64).unwrap(),
-            edit_date: doc.get(EDIT_DATE).and_then(Bson::as_i64).unwrap(),
-            restriction_reason: doc.get(RESTRICTION_REASON).and_then(Bson::as_str).unwrap().to_string(),
-            type_: doc.get(TYPE).and_then(Bson::as_str).unwrap().to_string(),
-            content: doc.get(CONTENT).and_then(Bson::as_str).unwrap().to_string(),
-            extra: doc.get(EXTRA).and_then(Bson::as_array).unwrap().iter().map(|bson| bson.as_str().unwrap().to_string()).collect(),
-            is_scam: doc.get(IS_SCAM).and_then(Bson::as_bool).unwrap(),
-            trigger: doc.get(TRIGGER).and_then(Bson::as_bool).unwrap()
-        }
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
     }
 
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: self.id,
-            SENDER: self.sender,
-            CHAT_ID: self.chat_id,
-            DATE: self.date,
-            EDIT_DATE: self.edit_date,
-            RESTRICTION_REASON: self.restriction_reason.to_string(),
-            TYPE: self.type_.to_string(),
-            CONTENT: self.content.to_string(),
-            EXTRA: self.extra.clone(),
-            IS_SCAM: self.is_scam,
-            TRIGGER: self.trigger
-        };
+    pub fn date(&self) -> i64 {
+        self.date
     }
 
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: self.id,
-                SENDER: self.sender,
-                CHAT_ID: self.chat_id,
-                DATE: self.date,
-                EDIT_DATE: self.edit_date,
-                RESTRICTION_REASON: self.restriction_reason.to_string(),
-                TYPE: self.type_.to_string(),
-                CONTENT: self.content.to_string(),
-                EXTRA: self.extra.clone(),
-                IS_SCAM: self.is_scam,
-                TRIGGER: self.trigger
-            }
-        };
+    pub fn is_trigger(&self) -> bool {
+        self.trigger
     }
 
-    fn collection_name(&self) -> &str {
-        COLLECTION
-    }
+}
 
-    fn id(&self) -> i64 {
-        self.id
-    }
+impl Merge for Message {
 
     fn merge(&mut self, new: &Self) -> &mut Self {
         self.content = new.content.clone();
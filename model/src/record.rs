@@ -1,14 +1,172 @@
-use mongodb::bson::Document;
+use std::fmt::{Display, Formatter};
+use mongodb::bson::{Document, doc};
 
-pub trait Record {
+pub static SCHEMA_VERSION: &str = "schema_version";
 
-    fn from_doc(doc: &Document) -> Self;
+/**
+*   Identity of a record in its collection.
+*   Telegram message ids are only unique within a chat, so message-like records are keyed by chat id and id.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Id(i64),
+    // (chat_id, id)
+    Chat(i64, i64)
+}
+
+impl Key {
+
+    pub fn id(&self) -> i64 {
+        match self {
+            Key::Id(id) => *id,
+            Key::Chat(_, id) => *id
+        }
+    }
+
+    pub fn to_doc(&self) -> Document {
+        match self {
+            Key::Id(id) => doc! { "id": id },
+            Key::Chat(chat_id, id) => doc! { "chat_id": chat_id, "id": id }
+        }
+    }
+
+}
+
+impl From<i64> for Key {
+    fn from(id: i64) -> Self {
+        Key::Id(id)
+    }
+}
+
+impl From<(i64, i64)> for Key {
+    fn from((chat_id, id): (i64, i64)) -> Self {
+        Key::Chat(chat_id, id)
+    }
+}
+
+/**
+*   Field of a stored document that can't be decoded
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    Missing { collection: &'static str, field: String },
+    WrongType { collection: &'static str, field: String, expected: &'static str },
+    // Wrong or missing encryption key, or altered value
+    Encrypted { collection: &'static str, field: String }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Missing { collection, field } => write!(f, "'{}' document is missing '{}'", collection, field),
+            DecodeError::WrongType { collection, field, expected } => write!(f, "'{}' of '{}' document isn't a {}", field, collection, expected),
+            DecodeError::Encrypted { collection, field } => write!(f, "'{}' of '{}' document can't be decrypted", field, collection)
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/**
+*   A document stored in a collection, usually implemented with `#[derive(Record)]`.
+*   Records are sent across the tasks handling the updates, hence `Send + Sync`.
+*/
+pub trait Record: Sized + Send + Sync {
+
+    // Version written in the `schema_version` field of the documents, bumped when their format changes
+    const SCHEMA_VERSION: i64 = 1;
+
+    // Fields encrypted with `cipher` once a key is set
+    const ENCRYPTED: &'static [&'static str] = &[];
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError>;
     fn to_doc(&self) -> Document;
-    fn to_doc_update(&self) -> Document;
 
-    fn collection_name(&self) -> &str;
+    fn to_doc_update(&self) -> Document {
+        doc! { "$set": self.to_doc() }
+    }
+
+    fn collection() -> &'static str;
+    fn collection_name(&self) -> &str {
+        Self::collection()
+    }
     fn id(&self) -> i64;
 
+    fn key(&self) -> Key {
+        Key::Id(self.id())
+    }
+
+    /**
+    *   Upgrades a document written with an older schema version, 0 when it predates versioning.
+    *   The runner then stamps it with the current version.
+    */
+    fn upgrade(_doc: &mut Document, _from: i64) {}
+
+}
+
+/**
+*   Update of a stored record with the fields of a fresher one
+*/
+pub trait Merge {
+
     fn merge(&mut self, new: &Self) -> &mut Self;
 
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use crate::{DecodeError, ForbiddenNames, Key, Keywords, KeywordMatch, Message, Record, User, Warning};
+
+    #[test]
+    fn derived_record_round_trip() {
+        let warning = Warning::from_doc(&Warning::new(10, -1, 5, 60).to_doc()).unwrap();
+
+        assert_eq!(warning.key(), Key::Chat(-1, 10));
+        assert_eq!(warning.reply_to(), 5);
+        assert!(warning.expire_date() > 0);
+        assert_eq!(Warning::collection(), crate::WARNINGS_COLLECTION);
+        assert_eq!(warning.to_doc().get_i64(crate::SCHEMA_VERSION).unwrap(), Warning::SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        let missing = User::from_doc(&doc! { "id": 42_i64 });
+        let wrong_type = User::from_doc(&doc! { "id": "42", "first_name": "Julie" });
+
+        assert_eq!(missing.err(), Some(DecodeError::Missing { collection: "users", field: String::from("first_name") }));
+        assert_eq!(wrong_type.err(), Some(DecodeError::WrongType { collection: "users", field: String::from("id"), expected: "integer" }));
+    }
+
+    #[test]
+    fn upgrade_fills_missing_defaults() {
+        let mut doc = doc! { "id": 1_i64, "sender": 2_i64, "chat_id": -1_i64, "date": 0_i64, "edit_date": 0_i64,
+            "restriction_reason": "", "type": "MessageText", "content": "Bonjour", "is_scam": false, "trigger": false };
+        assert!(Message::from_doc(&doc).is_ok());
+
+        Message::upgrade(&mut doc, 0);
+        assert!(doc.get_array("extra").unwrap().is_empty());
+        assert!(Message::from_doc(&doc).is_ok());
+    }
+
+    #[test]
+    fn missing_fields_take_their_default() {
+        let user = User::from_doc(&doc! { "id": 42_i64, "first_name": "Julie" }).unwrap();
+
+        assert_eq!(user.key(), Key::Id(42));
+        assert!(!user.is_scam_by_admin());
+        assert_eq!(user.to_doc().get_str("username").unwrap(), "");
+    }
+
+    #[test]
+    fn constant_id_and_decoded_fields() {
+        let keywords = Keywords::from_doc(&doc! { "fr": ["dispo"], "en": [], "de": [] }).unwrap();
+        let names = ForbiddenNames::new(vec![String::from("Mamadou")]);
+
+        assert!(matches!(keywords.text_match("Je suis dispo"), KeywordMatch::FrMatch));
+        assert_eq!(keywords.to_doc().get_i64("id").unwrap(), crate::KEYWORDS_ID);
+        assert_eq!(names.id(), crate::FORBIDDEN_NAMES_ID);
+        assert_eq!(names.to_doc_update().get_document("$set").unwrap().get_i64("id").unwrap(), crate::FORBIDDEN_NAMES_ID);
+    }
+
+}
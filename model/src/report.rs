@@ -0,0 +1,61 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{Merge, Record};
+
+pub static COLLECTION: &str = "reports";
+
+pub static KIND_SUPERGROUP_SPAM: &str = "supergroup-spam";
+pub static KIND_CHAT: &str = "chat";
+
+/**
+*   A report sent to Telegram, the id is the id of the reported message, or of the chat when the whole chat is reported
+*/
+#[derive(Record)]
+#[record(collection = COLLECTION)]
+pub struct Report {
+    id: i64,
+    #[record(chat_id)]
+    chat_id: i64,
+    #[record(default)]
+    sender: i64,
+    kind: String,
+    #[record(default)]
+    reason: String,
+    date: i64
+}
+
+impl Report {
+
+    pub fn new(id: i64, chat_id: i64, sender: i64, kind: &str, reason: &str) -> Self {
+        Report {
+            id,
+            chat_id,
+            sender,
+            kind: kind.to_string(),
+            reason: reason.to_string(),
+            date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+        }
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn date(&self) -> i64 {
+        self.date
+    }
+
+}
+
+impl Merge for Report {
+
+    fn merge(&mut self, new: &Self) -> &mut Self {
+        self.reason = new.reason.clone();
+        self.date = new.date;
+        self
+    }
+
+}
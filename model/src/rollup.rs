@@ -0,0 +1,263 @@
+use mongodb::bson::{Document, doc};
+use crate::{decode, require, DecodeError, Record, SCHEMA_VERSION};
+
+pub static COLLECTION: &str = "rollups";
+
+pub static ID: &str = "id";
+pub static DAY: &str = "day";
+pub static DIMENSION: &str = "dimension";
+pub static VALUE: &str = "value";
+pub static MESSAGES: &str = "messages";
+pub static THREATS: &str = "threats";
+pub static SCAMS: &str = "scams";
+pub static WARNINGS_SENT: &str = "warnings_sent";
+pub static WARNINGS_SUPPRESSED: &str = "warnings_suppressed";
+
+pub static DAY_SECONDS: i64 = 24 * 3600;
+
+/**
+*   What a rollup is counted by
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Chat,
+    ScamType,
+    Keyword,
+    Language
+}
+
+impl Dimension {
+
+    pub fn all() -> [Dimension; 4] {
+        [Dimension::Chat, Dimension::ScamType, Dimension::Keyword, Dimension::Language]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dimension::Chat => "chat",
+            Dimension::ScamType => "scam-type",
+            Dimension::Keyword => "keyword",
+            Dimension::Language => "language"
+        }
+    }
+
+    pub fn from(name: &str) -> Option<Self> {
+        Dimension::all().iter().cloned().find(|dimension| dimension.name() == name)
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    // Messages of the chat, only counted by chat
+    pub messages: i64,
+    // Messages analysed, only counted by chat
+    pub threats: i64,
+    pub scams: i64,
+    pub warnings_sent: i64,
+    // Warnings planned but not delivered, or replaced by other moderation actions
+    pub warnings_suppressed: i64
+}
+
+impl Counts {
+
+    pub fn add(&mut self, other: &Counts) -> &mut Self {
+        self.messages += other.messages;
+        self.threats += other.threats;
+        self.scams += other.scams;
+        self.warnings_sent += other.warnings_sent;
+        self.warnings_suppressed += other.warnings_suppressed;
+        self
+    }
+
+}
+
+/**
+*   Counters of a day for one value of a dimension, e.g. the scams of a chat on a given day.
+*   They're computed from the stored messages and sanctions, except the messages which are counted as they come.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollup {
+    // Midnight UTC
+    day: i64,
+    dimension: Dimension,
+    value: String,
+    counts: Counts
+}
+
+impl Rollup {
+
+    pub fn new(day: i64, dimension: Dimension, value: &str, counts: Counts) -> Self {
+        Rollup {
+            day: day_of(day),
+            dimension,
+            value: value.to_string(),
+            counts
+        }
+    }
+
+    pub fn day(&self) -> i64 {
+        self.day
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn counts(&self) -> &Counts {
+        &self.counts
+    }
+
+    fn counts_doc(&self) -> Document {
+        doc! {
+            THREATS: self.counts.threats,
+            SCAMS: self.counts.scams,
+            WARNINGS_SENT: self.counts.warnings_sent,
+            WARNINGS_SUPPRESSED: self.counts.warnings_suppressed
+        }
+    }
+
+}
+
+impl Record for Rollup {
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        let dimension = require::<String>(doc, COLLECTION, DIMENSION)?;
+        let count = |field: &str| decode::<i64>(doc, COLLECTION, field).map(Option::unwrap_or_default);
+        Ok(Rollup {
+            day: require(doc, COLLECTION, DAY)?,
+            dimension: Dimension::from(&dimension).ok_or(DecodeError::WrongType { collection: COLLECTION, field: DIMENSION.to_string(), expected: "dimension" })?,
+            value: require(doc, COLLECTION, VALUE)?,
+            counts: Counts {
+                messages: count(MESSAGES)?,
+                threats: count(THREATS)?,
+                scams: count(SCAMS)?,
+                warnings_sent: count(WARNINGS_SENT)?,
+                warnings_suppressed: count(WARNINGS_SUPPRESSED)?
+            }
+        })
+    }
+
+    fn to_doc(&self) -> Document {
+        let mut doc = doc! {
+            ID: self.id(),
+            DAY: self.day,
+            DIMENSION: self.dimension.name(),
+            VALUE: self.value.clone(),
+            MESSAGES: self.counts.messages
+        };
+        doc.extend(self.counts_doc());
+        doc.insert(SCHEMA_VERSION, Self::SCHEMA_VERSION);
+        doc
+    }
+
+    // The messages are counted apart, by `MessageSeen`
+    fn to_doc_update(&self) -> Document {
+        doc! { "$set": self.counts_doc() }
+    }
+
+    fn collection() -> &'static str {
+        COLLECTION
+    }
+
+    fn id(&self) -> i64 {
+        rollup_id(self.day, self.dimension, &self.value)
+    }
+
+}
+
+/**
+*   A message of a chat, counted in the chat's rollup of the day
+*/
+pub struct MessageSeen {
+    chat_id: i64,
+    date: i64
+}
+
+impl MessageSeen {
+
+    pub fn new(chat_id: i64, date: i64) -> Self {
+        MessageSeen {
+            chat_id,
+            date
+        }
+    }
+
+    fn rollup(&self) -> Rollup {
+        Rollup::new(self.date, Dimension::Chat, &self.chat_id.to_string(), Counts { messages: 1, ..Counts::default() })
+    }
+
+}
+
+impl Record for MessageSeen {
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        let rollup = Rollup::from_doc(doc)?;
+        Ok(MessageSeen {
+            chat_id: rollup.value.parse().unwrap_or(0),
+            date: rollup.day
+        })
+    }
+
+    fn to_doc(&self) -> Document {
+        self.rollup().to_doc()
+    }
+
+    fn to_doc_update(&self) -> Document {
+        doc! { "$inc": { MESSAGES: 1_i64 } }
+    }
+
+    fn collection() -> &'static str {
+        COLLECTION
+    }
+
+    fn id(&self) -> i64 {
+        self.rollup().id()
+    }
+
+}
+
+// Midnight UTC of the day of a timestamp
+pub fn day_of(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(DAY_SECONDS)
+}
+
+/**
+*   Id of the rollup of a day, dimension and value : a FNV-1a hash, stable across versions and platforms
+*/
+pub fn rollup_id(day: i64, dimension: Dimension, value: &str) -> i64 {
+    format!("{}/{}/{}", day_of(day), dimension.name(), value).bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Counts, Dimension, MessageSeen, Record, Rollup};
+
+    #[test]
+    fn rollups_of_the_same_day_share_their_id() {
+        let counts = Counts { scams: 2, warnings_sent: 1, ..Counts::default() };
+        let rollup = Rollup::new(86_400 * 3 + 60, Dimension::Keyword, "ESCORT", counts);
+
+        assert_eq!(rollup.day(), 86_400 * 3);
+        assert_eq!(rollup.id(), Rollup::new(86_400 * 4 - 1, Dimension::Keyword, "ESCORT", Counts::default()).id());
+        assert_ne!(rollup.id(), Rollup::new(86_400 * 3, Dimension::Language, "ESCORT", Counts::default()).id());
+        assert_eq!(Rollup::from_doc(&rollup.to_doc()).unwrap(), rollup);
+        assert!(!rollup.to_doc_update().get_document("$set").unwrap().contains_key("messages"));
+    }
+
+    #[test]
+    fn seen_messages_are_counted_in_the_chat_rollup() {
+        let seen = MessageSeen::new(-1, 86_400 + 10);
+        let rollup = Rollup::from_doc(&seen.to_doc()).unwrap();
+
+        assert_eq!(seen.id(), Rollup::new(86_400, Dimension::Chat, "-1", Counts::default()).id());
+        assert_eq!(rollup.counts().messages, 1);
+        assert_eq!(seen.to_doc_update().get_document("$inc").unwrap().get_i64("messages").unwrap(), 1);
+    }
+
+}
@@ -1,33 +1,88 @@
-use mongodb::bson::{Document, doc};
-use rtdlib::types::Message;
+use mongodb::bson::{Bson, Document, doc};
+use rtdlib::types::{Message, MessageSender, MessageSenderUser};
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::Record;
+use crate::{decode, require, DecodeError, Key, MessageSent, Record, SCHEMA_VERSION};
 
 pub static COLLECTION: &str = "stats";
 
 pub static ID: &str = "id";
+pub static CHAT_ID: &str = "chat_id";
 pub static MESSAGE: &str = "message";
+pub static SENDER: &str = "sender";
+pub static MESSAGE_DATE: &str = "message_date";
 pub static SCAM_TYPES: &str = "scam-types";
 pub static DATE: &str = "date";
-
+pub static ACTIONS: &str = "actions";
+// Set on the sanctions written before the composite keys whose chat couldn't be found back, their chat id is 0
+pub static CHAT_UNKNOWN: &str = "chat_unknown";
+
+// Fields of the scam types
+pub static TYPE: &str = "type";
+pub static KEYWORD: &str = "keyword";
+pub static LANGUAGE: &str = "language";
+pub static USER_ID: &str = "user_id";
+
+/**
+*   Scams found in a message and the actions taken against them.
+*   The delivery of the warning is written on the same document by `MessageSent`.
+*/
 pub struct Sanction {
     message: Message,
     scam_types: Vec<crate::sanction::ScamType>,
-    date: Option<u64>
+    date: Option<u64>,
+    actions: Vec<String>,
+    delivery: Option<MessageSent>
 }
 
+/**
+*   Why a message is a scam, with the evidence
+*/
+#[derive(Debug, Clone, PartialEq)]
 pub enum ScamType {
-    Keyword,
+    // The text contains a keyword of the language's list, the language is empty for built-in keywords
+    Keyword { keyword: String, language: String },
+    // The sender was flagged as a scammer
     Account(i64)
 }
 
+impl ScamType {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScamType::Keyword { .. } => "Keyword",
+            ScamType::Account(_) => "Account"
+        }
+    }
+
+    fn to_doc(&self) -> Document {
+        match self {
+            ScamType::Keyword { keyword, language } => doc! { TYPE: self.name(), KEYWORD: keyword.clone(), LANGUAGE: language.clone() },
+            ScamType::Account(user_id) => doc! { TYPE: self.name(), USER_ID: *user_id }
+        }
+    }
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        match require::<String>(doc, COLLECTION, TYPE)?.as_str() {
+            "Keyword" => Ok(ScamType::Keyword {
+                keyword: decode(doc, COLLECTION, KEYWORD)?.unwrap_or_default(),
+                language: decode(doc, COLLECTION, LANGUAGE)?.unwrap_or_default()
+            }),
+            "Account" => Ok(ScamType::Account(decode(doc, COLLECTION, USER_ID)?.unwrap_or(0))),
+            _ => Err(DecodeError::WrongType { collection: COLLECTION, field: SCAM_TYPES.to_string(), expected: "scam type" })
+        }
+    }
+
+}
+
 impl Sanction {
 
     pub fn new(message: Message, scam_types: Vec<crate::sanction::ScamType>) -> Self {
         Sanction {
             message,
             scam_types,
-            date: None
+            date: None,
+            actions: vec![],
+            delivery: None
         }
     }
 
@@ -35,6 +90,10 @@ impl Sanction {
         &self.message
     }
 
+    pub fn sender_id(&self) -> i64 {
+        self.message.sender().as_user().map_or(0, |user| user.user_id())
+    }
+
     pub fn scam_types(&self) -> &Vec<ScamType> {
         &self.scam_types
     }
@@ -43,86 +102,79 @@ impl Sanction {
         &self.date
     }
 
-    fn scam_types_to_str(&self) -> Vec<String> {
-        self.scam_types.iter()
-            .map(|st| match st {
-                ScamType::Keyword => String::from("Keyword"),
-                ScamType::Account(_) => String::from("Account")
-            })
-            .collect()
+    pub fn actions(&self) -> &Vec<String> {
+        &self.actions
     }
 
-}
-
-impl Record for Sanction {
-
-    // This method should never be called
-    fn from_doc(_doc: &Document) -> Self {
-        todo!()
+    pub fn set_actions(&mut self, actions: Vec<String>) -> &mut Self {
+        self.actions = actions;
+        self
     }
 
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: self.message.id(),
-            MESSAGE: self.message.id(),
-            SCAM_TYPES: self.scam_types_to_str(),
-            DATE: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-        };
+    // Only known once read back from the DB
+    pub fn delivery(&self) -> Option<&MessageSent> {
+        self.delivery.as_ref()
     }
 
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: self.message.id(),
-                MESSAGE: self.message.id(),
-                SCAM_TYPES: self.scam_types_to_str(),
-                DATE: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-            }
-        };
-    }
-
-    fn collection_name(&self) -> &str {
-        COLLECTION
-    }
-
-    fn id(&self) -> i64 {
-        self.message.id()
-    }
-
-    fn merge(&mut self, _new: &Self) -> &mut Self {
-        todo!()
+    // Seconds from the scam message to our warning, once delivered
+    pub fn reply_latency(&self) -> Option<i64> {
+        self.delivery.as_ref().filter(|delivery| delivery.is_sent()).map(MessageSent::latency)
     }
 
 }
 
-impl Record for &Sanction {
+impl Record for Sanction {
+
+    // Version 2 : sender, message date, and scam types with their evidence
+    const SCHEMA_VERSION: i64 = 2;
+
+    /**
+    *   The message only keeps what the document stores : its ids, sender and date
+    */
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        let message = Message::builder()
+            .id(require::<i64>(doc, COLLECTION, ID)?)
+            .chat_id(decode::<i64>(doc, COLLECTION, CHAT_ID)?.unwrap_or(0))
+            .sender(MessageSender::user(MessageSenderUser::builder().user_id(decode::<i64>(doc, COLLECTION, SENDER)?.unwrap_or(0)).build()))
+            .date(decode::<i64>(doc, COLLECTION, MESSAGE_DATE)?.unwrap_or(0))
+            .build();
+        let scam_types = decode::<Vec<Document>>(doc, COLLECTION, SCAM_TYPES)?.unwrap_or_default().iter()
+            .map(ScamType::from_doc)
+            .collect::<Result<Vec<ScamType>, DecodeError>>()?;
+        let delivery = match doc.contains_key(crate::stats::DELIVERY) {
+            true => Some(MessageSent::from_doc(doc)?),
+            false => None
+        };
 
-    // This method should never be called
-    fn from_doc(_doc: &Document) -> Self {
-        todo!()
+        Ok(Sanction {
+            message,
+            scam_types,
+            date: decode::<i64>(doc, COLLECTION, DATE)?.map(|date| date as u64),
+            actions: decode::<Vec<String>>(doc, COLLECTION, ACTIONS)?.unwrap_or_default(),
+            delivery
+        })
     }
 
     fn to_doc(&self) -> Document {
-        return doc! {
+        let mut doc = doc! {
             ID: self.message.id(),
+            CHAT_ID: self.message.chat_id(),
             MESSAGE: self.message.id(),
-            SCAM_TYPES: self.scam_types_to_str(),
-            DATE: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-        };
-    }
-
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: self.message.id(),
-                MESSAGE: self.message.id(),
-                SCAM_TYPES: self.scam_types_to_str(),
-                DATE: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-            }
+            SENDER: self.sender_id(),
+            MESSAGE_DATE: self.message.date(),
+            SCAM_TYPES: self.scam_types.iter().map(ScamType::to_doc).collect::<Vec<Document>>(),
+            DATE: self.date.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()) as i64,
+            ACTIONS: self.actions.clone(),
+            SCHEMA_VERSION: Self::SCHEMA_VERSION
         };
+        // The delivery writes itself, it's only kept here for sanctions read back
+        if let Some(delivery) = &self.delivery {
+            doc.insert(crate::stats::DELIVERY, delivery.delivery_doc());
+        }
+        doc
     }
 
-    fn collection_name(&self) -> &str {
+    fn collection() -> &'static str {
         COLLECTION
     }
 
@@ -130,8 +182,73 @@ impl Record for &Sanction {
         self.message.id()
     }
 
-    fn merge(&mut self, _new: &Self) -> &mut Self {
-        todo!()
+    fn key(&self) -> Key {
+        Key::Chat(self.message.chat_id(), self.message.id())
+    }
+
+    // Scam types were stored by name, without their evidence
+    fn upgrade(doc: &mut Document, from: i64) {
+        if from >= 2 {
+            return;
+        }
+        if let Ok(scam_types) = doc.get_array(SCAM_TYPES) {
+            let scam_types: Vec<Bson> = scam_types.iter()
+                .map(|scam_type| match scam_type {
+                    Bson::String(name) => Bson::Document(doc! { TYPE: name.clone() }),
+                    other => other.clone()
+                })
+                .collect();
+            doc.insert(SCAM_TYPES, scam_types);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use rtdlib::types::{Message, MessageSender, MessageSenderUser};
+    use crate::{MessageSent, Record, Sanction, ScamType};
+
+    #[test]
+    fn sanction_round_trip_with_evidence() {
+        let message = Message::builder()
+            .id(10)
+            .chat_id(-1)
+            .date(1000)
+            .sender(MessageSender::user(MessageSenderUser::builder().user_id(42).build()))
+            .build();
+        let scam_types = vec![ScamType::Keyword { keyword: String::from("ESCORT"), language: String::from("en") }, ScamType::Account(42)];
+        let mut sanction = Sanction::new(message, scam_types.clone());
+        sanction.set_actions(vec![String::from("reply")]);
+
+        let mut doc = sanction.to_doc();
+        doc.insert("delivery", MessageSent::sent(10, -1, 11, 30).to_doc().get_document("delivery").unwrap().clone());
+        let read = Sanction::from_doc(&doc).unwrap();
+
+        assert_eq!(read.scam_types(), &scam_types);
+        assert_eq!(read.sender_id(), 42);
+        assert_eq!(read.message().chat_id(), -1);
+        assert_eq!(read.actions(), &vec![String::from("reply")]);
+        assert!(read.date().is_some());
+        assert_eq!(read.reply_latency(), Some(30));
+    }
+
+    #[test]
+    fn sanctions_without_chat_are_read() {
+        let sanction = Sanction::from_doc(&doc! { "id": 10_i64, "message": 10_i64, "date": 1000_i64 }).unwrap();
+
+        assert_eq!(sanction.message().chat_id(), 0);
+    }
+
+    #[test]
+    fn upgrade_keeps_the_scam_type_names() {
+        let mut doc = doc! { "id": 10_i64, "chat_id": -1_i64, "message": 10_i64, "scam-types": ["Keyword", "Account"], "date": 1000_i64 };
+        Sanction::upgrade(&mut doc, 0);
+        let sanction = Sanction::from_doc(&doc).unwrap();
+
+        assert_eq!(sanction.scam_types().iter().map(ScamType::name).collect::<Vec<&str>>(), vec!["Keyword", "Account"]);
+        assert_eq!(sanction.reply_latency(), None);
     }
 
 }
@@ -1,28 +1,165 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 use mongodb::bson::{Document, doc};
+use crate::{decode, require, DecodeError, Key, Merge, Record, SCHEMA_VERSION};
+use crate::sanction::COLLECTION;
 
+pub static ID: &str = "id";
+pub static DELIVERY: &str = "delivery";
 pub static TIMESTAMP: &str = "timestamp";
 pub static IS_SENT: &str = "is_sent";
+pub static CHAT_ID: &str = "chat_id";
+pub static MESSAGE_ID: &str = "message_id";
+pub static ERROR_CODE: &str = "error_code";
+pub static ERROR_MESSAGE: &str = "error_message";
+pub static LATENCY: &str = "latency";
 
+/**
+*   Delivery outcome of a warning, stored in the `delivery` field of the sanction's stats document
+*/
 pub struct MessageSent {
+    // Id of the sanction, which is the id of the scam message
+    id: i64,
+    chat_id: i64,
+    // Id of the warning once sent, 0 otherwise
+    message_id: i64,
     timestamp: u64,
-    is_sent: bool
+    is_sent: bool,
+    error_code: i64,
+    error_message: String,
+    // Seconds from the scam message to the warning, once sent
+    latency: i64
 }
 
 impl MessageSent {
 
     pub fn new(is_sent: bool) -> Self {
         MessageSent {
+            id: 0,
+            chat_id: 0,
+            message_id: 0,
             timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-            is_sent
+            is_sent,
+            error_code: 0,
+            error_message: String::new(),
+            latency: 0
         }
     }
 
-    pub fn to_doc(&self) -> Document {
+    pub fn sent(id: i64, chat_id: i64, message_id: i64, latency: i64) -> Self {
+        MessageSent {
+            id,
+            chat_id,
+            message_id,
+            latency,
+            ..MessageSent::new(true)
+        }
+    }
+
+    pub fn failed(id: i64, chat_id: i64, error_code: i64, error_message: &str) -> Self {
+        MessageSent {
+            id,
+            chat_id,
+            error_code,
+            error_message: error_message.to_string(),
+            ..MessageSent::new(false)
+        }
+    }
+
+    pub fn is_sent(&self) -> bool {
+        self.is_sent
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn message_id(&self) -> i64 {
+        self.message_id
+    }
+
+    pub fn error_code(&self) -> i64 {
+        self.error_code
+    }
+
+    pub fn error_message(&self) -> &str {
+        &self.error_message
+    }
+
+    pub fn latency(&self) -> i64 {
+        self.latency
+    }
+
+    pub(crate) fn delivery_doc(&self) -> Document {
+        return doc! {
+            TIMESTAMP: self.timestamp as i64,
+            IS_SENT: self.is_sent,
+            CHAT_ID: self.chat_id,
+            MESSAGE_ID: self.message_id,
+            ERROR_CODE: self.error_code,
+            ERROR_MESSAGE: self.error_message.to_string(),
+            LATENCY: self.latency
+        };
+    }
+
+}
+
+impl Record for MessageSent {
+
+    fn from_doc(doc: &Document) -> Result<Self, DecodeError> {
+        let delivery: Document = require(doc, COLLECTION, DELIVERY)?;
+        Ok(MessageSent {
+            id: require(doc, COLLECTION, ID)?,
+            chat_id: decode(doc, COLLECTION, CHAT_ID)?.unwrap_or(0),
+            message_id: decode(&delivery, COLLECTION, MESSAGE_ID)?.unwrap_or(0),
+            timestamp: require::<i64>(&delivery, COLLECTION, TIMESTAMP)? as u64,
+            is_sent: require(&delivery, COLLECTION, IS_SENT)?,
+            error_code: decode(&delivery, COLLECTION, ERROR_CODE)?.unwrap_or(0),
+            error_message: decode(&delivery, COLLECTION, ERROR_MESSAGE)?.unwrap_or_default(),
+            latency: decode(&delivery, COLLECTION, LATENCY)?.unwrap_or(0)
+        })
+    }
+
+    fn to_doc(&self) -> Document {
+        return doc! {
+            ID: self.id,
+            CHAT_ID: self.chat_id,
+            DELIVERY: self.delivery_doc(),
+            SCHEMA_VERSION: Self::SCHEMA_VERSION
+        };
+    }
+
+    fn to_doc_update(&self) -> Document {
         return doc! {
-            TIMESTAMP: self.timestamp,
-            IS_SENT: self.is_sent
+            "$set": {
+                DELIVERY: self.delivery_doc()
+            }
         };
     }
 
+    fn collection() -> &'static str {
+        COLLECTION
+    }
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn key(&self) -> Key {
+        Key::Chat(self.chat_id, self.id)
+    }
+
+}
+
+impl Merge for MessageSent {
+
+    fn merge(&mut self, new: &Self) -> &mut Self {
+        self.message_id = new.message_id;
+        self.timestamp = new.timestamp;
+        self.is_sent = new.is_sent;
+        self.error_code = new.error_code;
+        self.error_message = new.error_message.clone();
+        self.latency = new.latency;
+        self
+    }
+
 }
@@ -1,36 +1,81 @@
-use mongodb::bson::{Bson, Document, doc};
+use std::time::{SystemTime, UNIX_EPOCH};
+use mongodb::bson::{Bson, doc};
 use rtdlib::types::UserType;
-use crate::Record;
+use crate::{Field, Merge, Record};
 
 pub static COLLECTION: &str = "users";
 
-pub static ID: &str = "id";
+// Fields of the name history
+pub static DATE: &str = "date";
 pub static FIRST_NAME: &str = "first_name";
 pub static LAST_NAME: &str = "last_name";
 pub static USERNAME: &str = "username";
-pub static PHONE_NUMBER: &str = "phone_number";
-pub static IS_VERIFIED: &str = "is_verified";
-pub static IS_SUPPORT: &str = "is_support";
-pub static RESTRICTION_REASON: &str = "restriction_reason";
-pub static IS_SCAM: &str = "is_scam";
-pub static USER_TYPE: &str = "user_type";
-pub static SCAM: &str = "scam";
-pub static BYPASS: &str = "bypass";
-
-#[derive(Debug)]
+
+#[derive(Debug, Record)]
+// Version 2 : encrypted phone number
+#[record(collection = COLLECTION, version = 2)]
 pub struct User {
     id: i64,
     pub first_name: String,
+    #[record(default)]
     last_name: String,
+    #[record(default)]
     username: String,
+    #[record(default, encrypt)]
     phone_number: String,
+    #[record(default)]
     is_verified: bool,
+    #[record(default)]
     is_support: bool,
+    #[record(default)]
     restriction_reason: String,
+    #[record(default)]
     is_scam: bool,
+    #[record(default)]
     user_type: String,
+    #[record(default)]
     pub scam: bool,
-    bypass: bool
+    #[record(default)]
+    bypass: bool,
+    // Former names, oldest first
+    #[record(default)]
+    names: Vec<NameChange>
+}
+
+/**
+*   Name a user had until `date`, when they renamed
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameChange {
+    pub date: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub username: String
+}
+
+impl Field for NameChange {
+
+    const TYPE: &'static str = "name change";
+
+    fn from_bson(bson: &Bson) -> Option<Self> {
+        let doc = bson.as_document()?;
+        Some(NameChange {
+            date: doc.get(DATE).and_then(i64::from_bson)?,
+            first_name: doc.get_str(FIRST_NAME).unwrap_or_default().to_string(),
+            last_name: doc.get_str(LAST_NAME).unwrap_or_default().to_string(),
+            username: doc.get_str(USERNAME).unwrap_or_default().to_string()
+        })
+    }
+
+    fn to_bson(&self) -> Bson {
+        Bson::Document(doc! {
+            DATE: self.date,
+            FIRST_NAME: self.first_name.clone(),
+            LAST_NAME: self.last_name.clone(),
+            USERNAME: self.username.clone()
+        })
+    }
+
 }
 
 impl User {
@@ -54,7 +99,35 @@ impl User {
                 _ => ""
             }.to_string(),
             scam,
-            bypass: false
+            bypass: false,
+            names: vec![]
+        }
+    }
+
+    // User we only know the id of
+    pub fn unknown(id: i64) -> Self {
+        User {
+            id,
+            first_name: String::new(),
+            last_name: String::new(),
+            username: String::new(),
+            phone_number: String::new(),
+            is_verified: false,
+            is_support: false,
+            restriction_reason: String::new(),
+            is_scam: false,
+            user_type: String::new(),
+            scam: false,
+            bypass: false,
+            names: vec![]
+        }
+    }
+
+    // User we only know the id of, flagged as a scammer
+    pub fn flagged(id: i64) -> Self {
+        User {
+            scam: true,
+            ..User::unknown(id)
         }
     }
 
@@ -66,75 +139,46 @@ impl User {
         self.bypass
     }
 
-}
-
-impl Record for User {
-
-    fn from_doc(doc: &Document) -> Self {
-        User {
-            id: doc.get(ID).and_then(Bson::as_i64).unwrap(),
-            first_name: doc.get(FIRST_NAME).and_then(Bson::as_str).unwrap().to_string(),
-            last_name: doc.get(LAST_NAME).and_then(Bson::as_str).or(Some("")).unwrap().to_string(),
-            username: doc.get(USERNAME).and_then(Bson::as_str).or(Some("")).unwrap().to_string(),
-            phone_number: doc.get(PHONE_NUMBER).and_then(Bson::as_str).or(Some("")).unwrap().to_string(),
-            is_verified: doc.get(IS_VERIFIED).and_then(Bson::as_bool).or(Some(false)).unwrap(),
-            is_support: doc.get(IS_SUPPORT).and_then(Bson::as_bool).or(Some(false)).unwrap(),
-            restriction_reason: doc.get(RESTRICTION_REASON).and_then(Bson::as_str).or(Some("")).unwrap().to_string(),
-            is_scam: doc.get(IS_SCAM).and_then(Bson::as_bool).or(Some(false)).unwrap(),
-            user_type: doc.get(USER_TYPE).and_then(Bson::as_str).or(Some("")).unwrap().to_string(),
-            scam: doc.get(SCAM).and_then(Bson::as_bool).or(Some(false)).unwrap(),
-            bypass: doc.get(BYPASS).and_then(Bson::as_bool).or(Some(false)).unwrap()
-        }
+    pub fn set_bypass(&mut self, bypass: bool) -> &mut Self {
+        self.bypass = bypass;
+        self
     }
 
-    fn to_doc(&self) -> Document {
-        return doc! {
-            ID: self.id,
-            FIRST_NAME: self.first_name.to_string(),
-            LAST_NAME: self.last_name.to_string(),
-            USERNAME: self.username.to_string(),
-            PHONE_NUMBER: self.phone_number.to_string(),
-            IS_VERIFIED: self.is_verified,
-            IS_SUPPORT: self.is_support,
-            RESTRICTION_REASON: self.restriction_reason.to_string(),
-            IS_SCAM: self.is_scam,
-            USER_TYPE: self.user_type.to_string(),
-            SCAM: self.scam,
-            BYPASS: self.bypass
-        };
+    pub fn last_name(&self) -> &str {
+        &self.last_name
     }
 
-    fn to_doc_update(&self) -> Document {
-        return doc! {
-            "$set": {
-                ID: self.id,
-                FIRST_NAME: self.first_name.to_string(),
-                LAST_NAME: self.last_name.to_string(),
-                USERNAME: self.username.to_string(),
-                PHONE_NUMBER: self.phone_number.to_string(),
-                IS_VERIFIED: self.is_verified,
-                IS_SUPPORT: self.is_support,
-                RESTRICTION_REASON: self.restriction_reason.to_string(),
-                IS_SCAM: self.is_scam,
-                USER_TYPE: self.user_type.to_string(),
-                SCAM: self.scam,
-                BYPASS: self.bypass
-            }
-        };
+    pub fn username(&self) -> &str {
+        &self.username
     }
 
-    fn collection_name(&self) -> &str {
-        COLLECTION
+    pub fn names(&self) -> &Vec<NameChange> {
+        &self.names
     }
 
-    fn id(&self) -> i64 {
-        self.id
+    // Renames since the given timestamp
+    pub fn renames_since(&self, since: i64) -> usize {
+        self.names.iter().filter(|name| name.date >= since).count()
     }
 
+}
+
+impl Merge for User {
+
     fn merge(&mut self, new: &Self) -> &mut Self {
         if self.phone_number.is_empty() {
             self.phone_number = new.phone_number.clone();
         }
+        // Users only known by their id have no name to keep
+        let known = !(self.first_name.is_empty() && self.last_name.is_empty() && self.username.is_empty());
+        if known && (self.first_name != new.first_name || self.last_name != new.last_name || self.username != new.username) {
+            self.names.push(NameChange {
+                date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+                first_name: self.first_name.clone(),
+                last_name: self.last_name.clone(),
+                username: self.username.clone()
+            });
+        }
         self.first_name = new.first_name.clone();
         self.last_name = new.last_name.clone();
         self.username = new.username.clone();
@@ -142,3 +186,33 @@ impl Record for User {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Merge, Record, User};
+
+    fn named(first_name: &str, username: &str) -> User {
+        let mut user = User::flagged(1);
+        user.first_name = first_name.to_string();
+        user.username = username.to_string();
+        user
+    }
+
+    #[test]
+    fn renames_are_kept_in_the_history() {
+        let mut user = User::flagged(1);
+        user.merge(&named("Julie", "julie"));
+        assert!(user.names().is_empty());
+
+        user.merge(&named("Julie", "julie"));
+        user.merge(&named("Sarah", "julie"));
+        user.merge(&named("Sarah", "sarah_dispo"));
+        let names: Vec<(&str, &str)> = user.names().iter().map(|name| (name.first_name.as_str(), name.username.as_str())).collect();
+        assert_eq!(names, vec![("Julie", "julie"), ("Sarah", "julie")]);
+        assert_eq!(user.renames_since(0), 2);
+
+        let read = User::from_doc(&user.to_doc()).unwrap();
+        assert_eq!(read.names(), user.names());
+    }
+
+}
@@ -0,0 +1,56 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{Merge, Record};
+
+pub static COLLECTION: &str = "warnings";
+
+/**
+*   A warning we sent, paired with the scam message it answers
+*/
+#[derive(Record)]
+#[record(collection = COLLECTION)]
+pub struct Warning {
+    id: i64,
+    #[record(chat_id)]
+    chat_id: i64,
+    reply_to: i64,
+    date: i64,
+    // 0 when the warning never expires
+    #[record(default)]
+    expire_date: i64
+}
+
+impl Warning {
+
+    pub fn new(id: i64, chat_id: i64, reply_to: i64, lifetime: u64) -> Self {
+        let date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        Warning {
+            id,
+            chat_id,
+            reply_to,
+            date,
+            expire_date: if lifetime == 0 { 0 } else { date + lifetime as i64 }
+        }
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn reply_to(&self) -> i64 {
+        self.reply_to
+    }
+
+    pub fn expire_date(&self) -> i64 {
+        self.expire_date
+    }
+
+}
+
+impl Merge for Warning {
+
+    fn merge(&mut self, new: &Self) -> &mut Self {
+        self.expire_date = new.expire_date;
+        self
+    }
+
+}